@@ -1,9 +1,17 @@
+use std::collections::HashSet;
+
 use tokio::fs;
 
-use crate::types::{Backend, Frontend, ServerSettings};
-use serde::Deserialize;
+use crate::{
+    proxy_service::{
+        backend_url::BackendUrl, forwarded::Cidr, grpc, header_rewriter::HeaderRewriter,
+        path_rewriter::PathRewriter,
+    },
+    types::{Backend, Frontend, ServerSettings},
+};
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct Config {
     #[serde(default)]
     pub server: ServerSettings,
@@ -11,23 +19,138 @@ pub struct Config {
     pub backends: Vec<Backend>,
 }
 
-pub async fn load_config() -> Result<Config, Box<dyn std::error::Error>> {
-    let file_path = match std::env::var("CONFIG_FILE") {
+/// Path of the config file, from `CONFIG_FILE`.
+pub fn config_path() -> String {
+    match std::env::var("CONFIG_FILE") {
         Ok(path) => path,
         Err(_) => "config.yaml".to_string(),
-    };
+    }
+}
 
+pub async fn load_config() -> Result<Config, Box<dyn std::error::Error>> {
+    load_config_from(&config_path()).await
+}
+
+pub async fn load_config_from(file_path: &str) -> Result<Config, Box<dyn std::error::Error>> {
     log::debug!("Loading config from: {}", file_path);
 
     let yaml_content = fs::read_to_string(file_path).await?;
 
     let config: Config = serde_yaml::from_str(&yaml_content)?;
 
+    validate(&config)?;
+
+    Ok(config)
+}
+
+/// Rejects configs that would otherwise fail while serving requests.
+fn validate(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
     if config.server.enable_https
         && (config.server.cert_path.is_none() || config.server.key_path.is_none())
     {
         return Err("cert_path and key_path must be provided when enable_https is true".into());
     }
 
-    Ok(config)
+    for trusted_proxy in &config.server.trusted_proxies {
+        trusted_proxy
+            .parse::<Cidr>()
+            .map_err(|e| format!("Invalid trusted proxy {:?}: {}", trusted_proxy, e))?;
+    }
+
+    let mut backend_names = HashSet::new();
+
+    for backend in &config.backends {
+        if !backend_names.insert(backend.name.as_str()) {
+            return Err(format!("Backend {:?} is defined more than once", backend.name).into());
+        }
+
+        HeaderRewriter::new([&backend.request_headers, &backend.response_headers])
+            .map_err(|e| format!("Invalid header rule in backend {:?}: {}", backend.name, e))?;
+
+        if let Some(health_check) = &backend.health_check {
+            if health_check.interval_ms == 0 {
+                return Err(format!(
+                    "health_check.interval_ms of backend {:?} must be greater than 0",
+                    backend.name
+                )
+                .into());
+            }
+            if health_check.healthy_threshold == 0 || health_check.unhealthy_threshold == 0 {
+                return Err(format!(
+                    "health_check thresholds of backend {:?} must be at least 1",
+                    backend.name
+                )
+                .into());
+            }
+        }
+
+        if let Some(outlier_detection) = &backend.outlier_detection {
+            if outlier_detection.consecutive_failures == 0 {
+                return Err(format!(
+                    "outlier_detection.consecutive_failures of backend {:?} must be at least 1",
+                    backend.name
+                )
+                .into());
+            }
+        }
+
+        for server in &backend.servers {
+            BackendUrl::parse(&server.server)
+                .and_then(|url| url.check_protocol(backend.protocol))
+                .map_err(|e| {
+                    format!(
+                        "Invalid server {:?} in backend {:?}: {}",
+                        server.server, backend.name, e
+                    )
+                })?;
+        }
+    }
+
+    for (index, frontend) in config.frontends.iter().enumerate() {
+        if !backend_names.contains(frontend.backend.as_str()) {
+            return Err(format!(
+                "Frontend {} references unknown backend {:?}",
+                frontend_label(index, frontend),
+                frontend.backend
+            )
+            .into());
+        }
+
+        for service in &frontend.grpc_services {
+            grpc::route_path(service).map_err(|e| {
+                format!(
+                    "Invalid gRPC service in frontend {}: {}",
+                    frontend_label(index, frontend),
+                    e
+                )
+            })?;
+        }
+
+        PathRewriter::new(frontend).map_err(|e| {
+            format!(
+                "Invalid rewrite rule in frontend {}: {}",
+                frontend_label(index, frontend),
+                e
+            )
+        })?;
+
+        HeaderRewriter::new([&frontend.request_headers, &frontend.response_headers]).map_err(
+            |e| {
+                format!(
+                    "Invalid header rule in frontend {}: {}",
+                    frontend_label(index, frontend),
+                    e
+                )
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+fn frontend_label(index: usize, frontend: &Frontend) -> String {
+    match &frontend.name {
+        Some(name) => format!("{:?}", name),
+        None => format!("#{} {:?}", index, frontend.path_prefix),
+    }
 }
@@ -0,0 +1,357 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, MutexGuard, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use tokio::{sync::Mutex, task::JoinHandle, time};
+
+use crate::{
+    config::{config_path, load_config_from, Config},
+    load_balancer::{
+        factory::{LoadBalancer, LoadBalancerFactory},
+        outlier_detection::OutlierDetector,
+    },
+    metrics::metrics,
+    proxy_service::{
+        access_log::AccessLogger, health_checker::HealthChecker, proxy_bridge::ProxyBridge,
+        proxy_handler::ProxyHandler,
+    },
+    telemetry::Tracer,
+    types::{Backend, BackendServer, Frontend},
+};
+
+/// Everything built from one version of the config. Requests keep the generation they started
+/// on, so a reload never changes the handling of a request in flight.
+pub struct Generation {
+    pub config: Config,
+    pub proxy_bridge: Arc<ProxyBridge>,
+    pub balancers: Vec<(String, Arc<dyn LoadBalancer>)>,
+    health_checks: Vec<JoinHandle<()>>,
+}
+
+impl Generation {
+    /// Builds the handlers for `config`. A backend whose `lb_algorithm` did not change keeps the
+    /// balancer of `previous`, so its servers keep their health and connection counts. The results
+    /// of the health checks are dropped when its `health_check` is removed or changed. Must be
+    /// called with [`Gateway::lock_servers`] held when `previous` is running.
+    fn build(
+        config: Config,
+        previous: Option<&Generation>,
+        access_log: Option<Arc<AccessLogger>>,
+        tracer: Option<Arc<Tracer>>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        type Balancer = (Arc<dyn LoadBalancer>, Option<Arc<OutlierDetector>>);
+
+        let mut balancers: HashMap<&str, Balancer> = HashMap::new();
+        let mut health_checkers = Vec::new();
+        let mut server_updates: Vec<(&str, Arc<dyn LoadBalancer>, Vec<BackendServer>)> = Vec::new();
+        let mut health_resets: Vec<Arc<dyn LoadBalancer>> = Vec::new();
+
+        for backend in &config.backends {
+            let previous_backend = previous.and_then(|previous| previous.backend(&backend.name));
+            let changed_at_runtime = previous_backend
+                .is_some_and(|(previous, balancer)| balancer.servers() != previous.servers);
+
+            let balancer = match previous_backend {
+                Some((previous, balancer)) if previous.lb_algorithm == backend.lb_algorithm => {
+                    // Changes made through the admin API are kept unless the config changes the
+                    // servers too.
+                    if previous.servers != backend.servers {
+                        server_updates.push((
+                            &backend.name,
+                            balancer.clone(),
+                            backend.servers.clone(),
+                        ));
+                    }
+                    // Servers marked down by checks that no longer run would never come back.
+                    if backend.health_check.is_none()
+                        || previous.health_check != backend.health_check
+                    {
+                        health_resets.push(balancer.clone());
+                    }
+                    balancer.clone()
+                }
+                _ => {
+                    if changed_at_runtime {
+                        log::warn!(
+                            "lb_algorithm of backend {:?} changed, reverting the server changes made through the admin API",
+                            backend.name
+                        );
+                    }
+                    LoadBalancerFactory::create(backend.lb_algorithm, backend.servers.clone())
+                }
+            };
+
+            if let Some(health_check) = &backend.health_check {
+                health_checkers.push(HealthChecker::new(
+                    backend.name.clone(),
+                    health_check.clone(),
+                    &backend.timeouts,
+                    backend.protocol,
+                    balancer.health(),
+                )?);
+            }
+
+            let outlier_detector = backend.outlier_detection.as_ref().map(|outlier_detection| {
+                Arc::new(OutlierDetector::new(
+                    backend.name.clone(),
+                    outlier_detection.clone(),
+                    balancer.health(),
+                ))
+            });
+
+            balancers.insert(backend.name.as_str(), (balancer, outlier_detector));
+        }
+
+        let proxy_handlers: Arc<Vec<(Frontend, Arc<ProxyHandler>)>> = Arc::new(
+            config
+                .frontends
+                .iter()
+                .map(|frontend| {
+                    let backend = config
+                        .backends
+                        .iter()
+                        .find(|backend| backend.name == frontend.backend)
+                        .ok_or_else(|| format!("Unknown backend {:?}", frontend.backend))?;
+                    let (balancer, outlier_detector) = &balancers[backend.name.as_str()];
+
+                    let handler = ProxyHandler::new(
+                        frontend,
+                        backend,
+                        balancer.clone(),
+                        outlier_detector.clone(),
+                    )?;
+                    Ok((frontend.clone(), Arc::new(handler)))
+                })
+                .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?,
+        );
+
+        let proxy_bridge = Arc::new(ProxyBridge::new(
+            proxy_handlers,
+            &config.server,
+            access_log,
+            tracer,
+        )?);
+
+        // Only touch the balancers shared with the running generation once nothing can fail.
+        for (backend, balancer, servers) in server_updates {
+            if let Some((previous, _)) = previous.and_then(|previous| previous.backend(backend)) {
+                if balancer.servers() != previous.servers {
+                    log::warn!(
+                        "Servers of backend {:?} changed in the config, reverting the changes made through the admin API",
+                        backend
+                    );
+                }
+            }
+            balancer.set_servers(servers);
+        }
+        for balancer in health_resets {
+            for (_, health) in balancer.health().servers() {
+                health.reset_checks();
+            }
+        }
+
+        let health_checks = health_checkers
+            .into_iter()
+            .map(HealthChecker::spawn)
+            .collect();
+
+        let balancers = config
+            .backends
+            .iter()
+            .map(|backend| {
+                let (balancer, _) = &balancers[backend.name.as_str()];
+                (backend.name.clone(), balancer.clone())
+            })
+            .collect();
+
+        Ok(Self {
+            config,
+            proxy_bridge,
+            balancers,
+            health_checks,
+        })
+    }
+
+    /// The config and balancer of the backend named `name`.
+    fn backend(&self, name: &str) -> Option<(&Backend, &Arc<dyn LoadBalancer>)> {
+        let backend = self.config.backends.iter().find(|b| b.name == name)?;
+        Some((backend, self.balancer(name)?))
+    }
+
+    pub fn balancer(&self, backend: &str) -> Option<&Arc<dyn LoadBalancer>> {
+        self.balancers
+            .iter()
+            .find(|(name, _)| name == backend)
+            .map(|(_, balancer)| balancer)
+    }
+}
+
+impl Drop for Generation {
+    fn drop(&mut self) {
+        for health_check in &self.health_checks {
+            health_check.abort();
+        }
+    }
+}
+
+/// Holds the current generation and replaces it when the config is reloaded.
+pub struct Gateway {
+    current: RwLock<Arc<Generation>>,
+    access_log: Option<Arc<AccessLogger>>,
+    tracer: Option<Arc<Tracer>>,
+    reloading: Mutex<()>,
+    /// Serializes changes to the server sets, made by reloads and the admin API.
+    servers: std::sync::Mutex<()>,
+}
+
+impl Gateway {
+    pub fn new(
+        config: Config,
+        access_log: Option<Arc<AccessLogger>>,
+        tracer: Option<Arc<Tracer>>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let generation = Generation::build(config, None, access_log.clone(), tracer.clone())?;
+        metrics().set_balancers(generation.balancers.clone());
+
+        Ok(Self {
+            current: RwLock::new(Arc::new(generation)),
+            access_log,
+            tracer,
+            reloading: Mutex::new(()),
+            servers: std::sync::Mutex::new(()),
+        })
+    }
+
+    /// Held while the servers of a balancer are read and replaced.
+    pub fn lock_servers(&self) -> MutexGuard<'_, ()> {
+        self.servers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn current(&self) -> Arc<Generation> {
+        self.current.read().unwrap().clone()
+    }
+
+    pub fn proxy_bridge(&self) -> Arc<ProxyBridge> {
+        self.current.read().unwrap().proxy_bridge.clone()
+    }
+
+    /// Loads the config file again and swaps in a new generation. An invalid config is logged and
+    /// the current one is kept.
+    pub async fn reload(&self) {
+        self.reload_from(&config_path()).await
+    }
+
+    /// Like [`Gateway::reload`], reading the config from `path`.
+    pub async fn reload_from(&self, path: &str) {
+        let _reloading = self.reloading.lock().await;
+
+        let config = match load_config_from(path).await {
+            Ok(config) => config,
+            Err(e) => {
+                log::error!("Failed to reload config, keeping the current one: {}", e);
+                return;
+            }
+        };
+
+        let _servers = self.lock_servers();
+        let current = self.current();
+        warn_restart_required(&current.config, &config);
+
+        match Generation::build(
+            config,
+            Some(&current),
+            self.access_log.clone(),
+            self.tracer.clone(),
+        ) {
+            Ok(generation) => {
+                metrics().set_balancers(generation.balancers.clone());
+                *self.current.write().unwrap() = Arc::new(generation);
+                log::info!("Reloaded config from {}", path);
+            }
+            Err(e) => log::error!("Failed to reload config, keeping the current one: {}", e),
+        }
+    }
+
+    /// Reloads on `SIGHUP` and, unless `watch_interval` is zero, when the config file changes.
+    pub fn watch(self: &Arc<Self>, watch_interval: Duration) -> std::io::Result<()> {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+
+            let mut hangup = signal(SignalKind::hangup())?;
+            let gateway = self.clone();
+            tokio::spawn(async move {
+                while hangup.recv().await.is_some() {
+                    log::info!("Received SIGHUP, reloading config");
+                    gateway.reload().await;
+                }
+            });
+        }
+
+        if !watch_interval.is_zero() {
+            let gateway = self.clone();
+            tokio::spawn(async move {
+                let path = config_path();
+                let mut last_modified = modified(&path).await;
+
+                loop {
+                    time::sleep(watch_interval).await;
+
+                    let modified = modified(&path).await;
+                    if modified != last_modified {
+                        last_modified = modified;
+                        log::info!("Config file {} changed, reloading", path);
+                        gateway.reload().await;
+                    }
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+async fn modified(path: &str) -> Option<SystemTime> {
+    tokio::fs::metadata(path).await.ok()?.modified().ok()
+}
+
+/// Settings used when the listeners start are not reloaded.
+fn warn_restart_required(current: &Config, next: &Config) {
+    let (current, next) = (&current.server, &next.server);
+
+    let changed = [
+        ("port", current.port != next.port),
+        ("enable_https", current.enable_https != next.enable_https),
+        (
+            "cert_path/key_path",
+            current.cert_path != next.cert_path || current.key_path != next.key_path,
+        ),
+        (
+            "admin",
+            current.admin.as_ref().map(|admin| admin.address)
+                != next.admin.as_ref().map(|admin| admin.address),
+        ),
+        (
+            "access_log",
+            serde_json::to_value(&current.access_log).ok()
+                != serde_json::to_value(&next.access_log).ok(),
+        ),
+        (
+            "config_watch_interval_ms",
+            current.config_watch_interval_ms != next.config_watch_interval_ms,
+        ),
+        (
+            "tracing",
+            serde_json::to_value(&current.tracing).ok() != serde_json::to_value(&next.tracing).ok(),
+        ),
+    ];
+
+    for (setting, _) in changed.iter().filter(|(_, changed)| *changed) {
+        log::warn!(
+            "Changes to server.{} take effect after a restart, keeping the current value",
+            setting
+        );
+    }
+}
@@ -1,5 +1,6 @@
 pub mod load_balancer;
 pub use load_balancer::factory::{LoadBalancer, LoadBalancerFactory, SelectedLB};
+pub use load_balancer::health::HealthRegistry;
 pub use load_balancer::least_connections_lb::LeastConnectionsStrategy;
 pub use load_balancer::round_robin_lb::RoundRobinStrategy;
 pub use load_balancer::weighted_round_robin_lb::WeightedRoundRobin;
@@ -8,3 +9,13 @@ pub mod types;
 pub use types::LbAlgorithm;
 
 pub mod proxy_service;
+
+pub mod metrics;
+
+pub mod telemetry;
+
+pub mod config;
+
+pub mod gateway;
+
+pub mod server;
@@ -2,8 +2,8 @@ use crate::types::{BackendServer, LbAlgorithm};
 use std::sync::Arc;
 
 use super::{
-    least_connections_lb::LeastConnectionsStrategy, round_robin_lb::RoundRobinStrategy,
-    weighted_round_robin_lb::WeightedRoundRobin,
+    health::HealthRegistry, least_connections_lb::LeastConnectionsStrategy,
+    round_robin_lb::RoundRobinStrategy, weighted_round_robin_lb::WeightedRoundRobin,
 };
 
 pub struct SelectedLB {
@@ -21,6 +21,20 @@ impl Drop for SelectedLB {
 #[async_trait::async_trait]
 pub trait LoadBalancer: Send + Sync {
     async fn next(&self) -> Option<Arc<SelectedLB>>;
+
+    fn health(&self) -> Arc<HealthRegistry>;
+
+    /// Requests currently sent to each server, for strategies that track them.
+    fn connections(&self) -> Vec<(String, usize)> {
+        Vec::new()
+    }
+
+    /// Servers currently balanced over.
+    fn servers(&self) -> Vec<BackendServer>;
+
+    /// Atomically replaces the servers. Servers that remain keep their health and connection
+    /// counts, and requests in flight to removed servers complete normally.
+    fn set_servers(&self, servers: Vec<BackendServer>);
 }
 
 pub struct LoadBalancerFactory;
@@ -0,0 +1,148 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::Instant,
+};
+
+pub struct ServerHealth {
+    healthy: AtomicBool,
+    consecutive_successes: AtomicU32,
+    consecutive_failures: AtomicU32,
+    ejected_until: Mutex<Option<Instant>>,
+    draining: AtomicBool,
+}
+
+impl ServerHealth {
+    fn new() -> Self {
+        Self {
+            healthy: AtomicBool::new(true),
+            consecutive_successes: AtomicU32::new(0),
+            consecutive_failures: AtomicU32::new(0),
+            ejected_until: Mutex::new(None),
+            draining: AtomicBool::new(false),
+        }
+    }
+
+    /// A server is healthy when the active checks pass and it is not currently ejected.
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed) && !self.is_ejected()
+    }
+
+    pub fn is_ejected(&self) -> bool {
+        match *self.ejected_until.lock().unwrap() {
+            Some(until) => Instant::now() < until,
+            None => false,
+        }
+    }
+
+    /// A draining server keeps its in-flight requests but receives no new ones.
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    pub fn set_draining(&self, draining: bool) {
+        self.draining.store(draining, Ordering::Relaxed);
+    }
+
+    pub fn eject(&self, until: Instant) {
+        *self.ejected_until.lock().unwrap() = Some(until);
+    }
+
+    /// Forgets the results of the active checks. Ejections and draining are kept.
+    pub fn reset_checks(&self) {
+        self.healthy.store(true, Ordering::Relaxed);
+        self.consecutive_successes.store(0, Ordering::Relaxed);
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+    }
+
+    /// Records a successful probe, returns true when the server transitioned to healthy.
+    pub fn record_success(&self, healthy_threshold: u32) -> bool {
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+        let successes = self.consecutive_successes.fetch_add(1, Ordering::Relaxed) + 1;
+
+        successes >= healthy_threshold && !self.healthy.swap(true, Ordering::Relaxed)
+    }
+
+    /// Records a failed probe, returns true when the server transitioned to unhealthy.
+    pub fn record_failure(&self, unhealthy_threshold: u32) -> bool {
+        self.consecutive_successes.store(0, Ordering::Relaxed);
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+
+        failures >= unhealthy_threshold && self.healthy.swap(false, Ordering::Relaxed)
+    }
+}
+
+pub struct HealthRegistry {
+    servers: RwLock<HashMap<String, Arc<ServerHealth>>>,
+}
+
+impl HealthRegistry {
+    pub fn new<'a>(servers: impl IntoIterator<Item = &'a String>) -> Self {
+        Self {
+            servers: RwLock::new(
+                servers
+                    .into_iter()
+                    .map(|server| (server.clone(), Arc::new(ServerHealth::new())))
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn is_healthy(&self, server: &str) -> bool {
+        self.get(server)
+            .map(|health| health.is_healthy())
+            .unwrap_or(true)
+    }
+
+    /// Whether new requests may be sent to the server: it is healthy and not draining.
+    pub fn is_available(&self, server: &str) -> bool {
+        self.get(server)
+            .map(|health| health.is_healthy() && !health.is_draining())
+            .unwrap_or(true)
+    }
+
+    pub fn get(&self, server: &str) -> Option<Arc<ServerHealth>> {
+        self.servers.read().unwrap().get(server).cloned()
+    }
+
+    pub fn set_healthy(&self, server: &str, healthy: bool) {
+        if let Some(health) = self.get(server) {
+            health.healthy.store(healthy, Ordering::Relaxed);
+        }
+    }
+
+    pub fn servers(&self) -> Vec<(String, Arc<ServerHealth>)> {
+        self.servers
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(server, health)| (server.clone(), health.clone()))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.servers.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Tracks exactly `servers`. Servers that were already tracked keep their state.
+    pub fn set_servers<'a>(&self, servers: impl IntoIterator<Item = &'a String>) {
+        let mut current = self.servers.write().unwrap();
+        let mut next = HashMap::new();
+
+        for server in servers {
+            let health = current
+                .remove(server)
+                .unwrap_or_else(|| Arc::new(ServerHealth::new()));
+            next.insert(server.clone(), health);
+        }
+
+        *current = next;
+    }
+}
@@ -1,9 +1,18 @@
-use std::sync::{atomic::AtomicUsize, Arc};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, RwLock,
+};
 
-use super::factory::{LoadBalancer, SelectedLB};
+use crate::types::BackendServer;
+
+use super::{
+    factory::{LoadBalancer, SelectedLB},
+    health::HealthRegistry,
+};
 
 pub struct LeastConnectionsStrategy {
-    pub servers: Vec<(String, Arc<AtomicUsize>)>,
+    servers: RwLock<Vec<(BackendServer, Arc<AtomicUsize>)>>,
+    health: Arc<HealthRegistry>,
 }
 
 impl LeastConnectionsStrategy {
@@ -13,10 +22,21 @@ impl LeastConnectionsStrategy {
             servers
         );
         Self {
-            servers: servers
-                .into_iter()
-                .map(|s| (s, Arc::new(AtomicUsize::new(0))))
-                .collect(),
+            health: Arc::new(HealthRegistry::new(&servers)),
+            servers: RwLock::new(
+                servers
+                    .into_iter()
+                    .map(|server| {
+                        (
+                            BackendServer {
+                                server,
+                                weight: None,
+                            },
+                            Arc::new(AtomicUsize::new(0)),
+                        )
+                    })
+                    .collect(),
+            ),
         }
     }
 }
@@ -24,26 +44,77 @@ impl LeastConnectionsStrategy {
 #[async_trait::async_trait]
 impl LoadBalancer for LeastConnectionsStrategy {
     async fn next(&self) -> Option<Arc<SelectedLB>> {
-        let (server, connections) = self.servers.iter().min_by_key(|(_, connections)| {
-            connections.load(std::sync::atomic::Ordering::Relaxed)
-        })?;
+        let (server, connections) = {
+            let servers = self.servers.read().unwrap();
+            let (server, connections) = servers
+                .iter()
+                .filter(|(server, _)| self.health.is_available(&server.server))
+                .min_by_key(|(_, connections)| connections.load(Ordering::Relaxed))?;
+            (server.server.clone(), connections.clone())
+        };
 
         log::debug!(
             "LeastConnectionsStrategy selected server: {}, current connections: {}",
             server,
-            connections.load(std::sync::atomic::Ordering::Relaxed)
+            connections.load(Ordering::Relaxed)
         );
 
-        connections.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-        let connections_clone = Arc::clone(connections);
+        connections.fetch_add(1, Ordering::Relaxed);
 
         let clean_up_fn = Box::new(move || {
-            connections_clone.fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+            connections.fetch_sub(1, Ordering::Relaxed);
         });
 
         Some(Arc::new(SelectedLB {
-            server: server.clone(),
+            server,
             cleanup_fn: clean_up_fn,
         }))
     }
+
+    fn health(&self) -> Arc<HealthRegistry> {
+        self.health.clone()
+    }
+
+    fn connections(&self) -> Vec<(String, usize)> {
+        self.servers
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(server, connections)| {
+                (server.server.clone(), connections.load(Ordering::Relaxed))
+            })
+            .collect()
+    }
+
+    fn servers(&self) -> Vec<BackendServer> {
+        self.servers
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(server, _)| server.clone())
+            .collect()
+    }
+
+    /// Servers that remain keep their counter, so requests in flight are still released from it.
+    fn set_servers(&self, servers: Vec<BackendServer>) {
+        log::info!(
+            "LeastConnectionsStrategy servers replaced with: {:?}",
+            servers
+        );
+        let mut current = self.servers.write().unwrap();
+        self.health
+            .set_servers(servers.iter().map(|server| &server.server));
+
+        *current = servers
+            .into_iter()
+            .map(|server| {
+                let connections = current
+                    .iter()
+                    .find(|(existing, _)| existing.server == server.server)
+                    .map(|(_, connections)| connections.clone())
+                    .unwrap_or_default();
+                (server, connections)
+            })
+            .collect();
+    }
 }
@@ -1,4 +1,6 @@
 pub mod factory;
+pub mod health;
+pub mod outlier_detection;
 
 pub mod least_connections_lb;
 pub mod round_robin_lb;
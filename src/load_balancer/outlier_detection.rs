@@ -0,0 +1,165 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::types::OutlierDetection;
+
+use super::health::HealthRegistry;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Success,
+    Failure,
+}
+
+#[derive(Default)]
+struct OutlierState {
+    consecutive_failures: u32,
+    outcomes: VecDeque<(Instant, Outcome)>,
+    ejections: u32,
+    last_ejected_until: Option<Instant>,
+}
+
+impl OutlierState {
+    fn record(&mut self, now: Instant, outcome: Outcome, window: Duration) {
+        match outcome {
+            Outcome::Success => self.consecutive_failures = 0,
+            Outcome::Failure => self.consecutive_failures += 1,
+        }
+
+        self.outcomes.push_back((now, outcome));
+        while let Some((at, _)) = self.outcomes.front() {
+            if now.duration_since(*at) <= window {
+                break;
+            }
+            self.outcomes.pop_front();
+        }
+    }
+
+    fn failure_rate(&self) -> f64 {
+        if self.outcomes.is_empty() {
+            return 0.0;
+        }
+
+        let failures = self
+            .outcomes
+            .iter()
+            .filter(|(_, outcome)| *outcome == Outcome::Failure)
+            .count();
+
+        failures as f64 / self.outcomes.len() as f64
+    }
+}
+
+/// Ejects servers based on the outcome of proxied requests.
+pub struct OutlierDetector {
+    backend: String,
+    config: OutlierDetection,
+    registry: Arc<HealthRegistry>,
+    states: Mutex<HashMap<String, OutlierState>>,
+}
+
+impl OutlierDetector {
+    pub fn new(backend: String, config: OutlierDetection, registry: Arc<HealthRegistry>) -> Self {
+        log::info!(
+            "Outlier detection enabled for backend: {}, config: {:?}",
+            backend,
+            config
+        );
+
+        Self {
+            backend,
+            config,
+            registry,
+            states: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn report(&self, server: &str, outcome: Outcome) {
+        let now = Instant::now();
+        let window = Duration::from_millis(self.config.window_ms);
+
+        let mut states = self.states.lock().unwrap();
+        let state = states.entry(server.to_string()).or_default();
+
+        if outcome == Outcome::Success && state.ejections > 0 {
+            let max_ejection = Duration::from_millis(self.config.max_ejection_ms);
+            if let Some(until) = state.last_ejected_until {
+                if now > until + max_ejection {
+                    state.ejections = 0;
+                }
+            }
+        }
+
+        state.record(now, outcome, window);
+
+        if outcome == Outcome::Success || !self.is_outlier(state) {
+            return;
+        }
+
+        let Some(health) = self.registry.get(server) else {
+            return;
+        };
+
+        if health.is_ejected() {
+            return;
+        }
+
+        if !self.can_eject() {
+            log::warn!(
+                "Server {} in backend {} is an outlier, but the ejection limit has been reached",
+                server,
+                self.backend
+            );
+            return;
+        }
+
+        let ejection = Duration::from_millis(self.config.base_ejection_ms)
+            .saturating_mul(2u32.saturating_pow(state.ejections))
+            .min(Duration::from_millis(self.config.max_ejection_ms));
+        let until = now + ejection;
+
+        health.eject(until);
+        state.ejections += 1;
+        state.last_ejected_until = Some(until);
+        state.consecutive_failures = 0;
+        state.outcomes.clear();
+
+        log::warn!(
+            "Ejected server {} from backend {} for {:?}",
+            server,
+            self.backend,
+            ejection
+        );
+    }
+
+    fn is_outlier(&self, state: &OutlierState) -> bool {
+        if state.consecutive_failures >= self.config.consecutive_failures {
+            return true;
+        }
+
+        match self.config.failure_rate_threshold {
+            Some(threshold) => {
+                state.outcomes.len() >= self.config.min_requests as usize
+                    && state.failure_rate() > threshold
+            }
+            None => false,
+        }
+    }
+
+    /// Never eject past the configured percentage, and never eject the last available server.
+    /// Ejected, unhealthy and draining servers all count as unavailable.
+    fn can_eject(&self) -> bool {
+        let servers = self.registry.servers();
+        let total = servers.len();
+        let unavailable = servers
+            .iter()
+            .filter(|(_, health)| !health.is_healthy() || health.is_draining())
+            .count();
+
+        unavailable + 1 < total
+            && (unavailable + 1) * 100 <= total * self.config.max_ejection_percent as usize
+    }
+}
@@ -1,20 +1,35 @@
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
-    Arc,
+    Arc, RwLock,
 };
 
-use super::factory::{LoadBalancer, SelectedLB};
+use crate::types::BackendServer;
+
+use super::{
+    factory::{LoadBalancer, SelectedLB},
+    health::HealthRegistry,
+};
 
 pub struct RoundRobinStrategy {
-    servers: Vec<String>,
+    servers: RwLock<Vec<BackendServer>>,
     current: AtomicUsize,
+    health: Arc<HealthRegistry>,
 }
 
 impl RoundRobinStrategy {
     pub fn new(servers: Vec<String>) -> Self {
         log::info!("RoundRobinStrategy initialized with servers: {:?}", servers);
         Self {
-            servers,
+            health: Arc::new(HealthRegistry::new(&servers)),
+            servers: RwLock::new(
+                servers
+                    .into_iter()
+                    .map(|server| BackendServer {
+                        server,
+                        weight: None,
+                    })
+                    .collect(),
+            ),
             current: AtomicUsize::new(0),
         }
     }
@@ -23,16 +38,38 @@ impl RoundRobinStrategy {
 #[async_trait::async_trait]
 impl LoadBalancer for RoundRobinStrategy {
     async fn next(&self) -> Option<Arc<SelectedLB>> {
-        let current = self.current.fetch_add(1, Ordering::Relaxed);
-        let server = self.servers.get(current % self.servers.len())?;
+        let server = {
+            let servers = self.servers.read().unwrap();
+            (0..servers.len()).find_map(|_| {
+                let current = self.current.fetch_add(1, Ordering::Relaxed);
+                let server = &servers[current % servers.len()].server;
+                self.health.is_available(server).then(|| server.clone())
+            })?
+        };
 
         log::debug!("RoundRobinStrategy selected server: {}", server);
 
         let empty_fn = Box::new(move || {});
 
         Some(Arc::new(SelectedLB {
-            server: server.clone(),
+            server,
             cleanup_fn: empty_fn,
         }))
     }
+
+    fn health(&self) -> Arc<HealthRegistry> {
+        self.health.clone()
+    }
+
+    fn servers(&self) -> Vec<BackendServer> {
+        self.servers.read().unwrap().clone()
+    }
+
+    fn set_servers(&self, servers: Vec<BackendServer>) {
+        log::info!("RoundRobinStrategy servers replaced with: {:?}", servers);
+        let mut current = self.servers.write().unwrap();
+        self.health
+            .set_servers(servers.iter().map(|server| &server.server));
+        *current = servers;
+    }
 }
@@ -1,47 +1,84 @@
 use crate::types::BackendServer;
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
-    Arc,
+    Arc, RwLock,
 };
 
-use super::factory::{LoadBalancer, SelectedLB};
+use super::{
+    factory::{LoadBalancer, SelectedLB},
+    health::HealthRegistry,
+};
 
-pub struct WeightedRoundRobin {
+struct WeightedServers {
     servers: Vec<(String, u32)>,
-    current: AtomicUsize,
     total_weight: u32,
 }
 
+impl WeightedServers {
+    fn new(servers: Vec<BackendServer>) -> Self {
+        let servers = servers
+            .into_iter()
+            .map(|server| (server.server, server.weight.unwrap_or(1)))
+            .collect::<Vec<_>>();
+
+        Self {
+            total_weight: servers.iter().map(|(_, weight)| weight).sum(),
+            servers,
+        }
+    }
+}
+
+pub struct WeightedRoundRobin {
+    servers: RwLock<WeightedServers>,
+    current: AtomicUsize,
+    health: Arc<HealthRegistry>,
+}
+
 impl WeightedRoundRobin {
     pub fn new(servers: Vec<BackendServer>) -> Self {
-        let total_weight = servers
-            .iter()
-            .map(|server| server.weight.unwrap_or(1))
-            .sum();
+        let health = Arc::new(HealthRegistry::new(
+            servers.iter().map(|server| &server.server),
+        ));
+        let servers = WeightedServers::new(servers);
         log::info!(
             "WeightedRoundRobinStrategy initialized with servers: {:?}, total_weight: {}",
-            servers,
-            total_weight
+            servers.servers,
+            servers.total_weight
         );
         Self {
-            servers: servers
-                .into_iter()
-                .map(|server| (server.server, server.weight.unwrap_or(1)))
-                .collect(),
+            health,
+            servers: RwLock::new(servers),
             current: AtomicUsize::new(0),
-            total_weight,
         }
     }
 
-    fn next_server(&self) -> Option<Arc<(&String, &u32)>> {
+    fn next_server(&self) -> Option<String> {
+        let servers = self.servers.read().unwrap();
+
+        let healthy_servers = servers
+            .servers
+            .iter()
+            .filter(|(server, _)| self.health.is_available(server))
+            .collect::<Vec<_>>();
+
+        let healthy_weight: u32 = if healthy_servers.len() == servers.servers.len() {
+            servers.total_weight
+        } else {
+            healthy_servers.iter().map(|(_, weight)| *weight).sum()
+        };
+
+        if healthy_weight == 0 {
+            return None;
+        }
+
         let mut current = self.current.fetch_add(1, Ordering::Relaxed);
-        current %= self.total_weight as usize;
+        current %= healthy_weight as usize;
 
         let mut cumulative_weight = 0;
-        for (server, weight) in &self.servers {
+        for (server, weight) in healthy_servers {
             cumulative_weight += *weight;
             if current < cumulative_weight as usize {
-                return Some(Arc::new((server, weight)));
+                return Some(server.clone());
             }
         }
 
@@ -52,15 +89,45 @@ impl WeightedRoundRobin {
 #[async_trait::async_trait]
 impl LoadBalancer for WeightedRoundRobin {
     async fn next(&self) -> Option<Arc<SelectedLB>> {
-        let (server, _) = *self.next_server()?;
+        let server = self.next_server()?;
 
         log::debug!("WeightedRoundRobin selected server: {}", server);
 
         let empty_fn = Box::new(move || {});
 
         Some(Arc::new(SelectedLB {
-            server: server.clone(),
+            server,
             cleanup_fn: empty_fn,
         }))
     }
+
+    fn health(&self) -> Arc<HealthRegistry> {
+        self.health.clone()
+    }
+
+    fn servers(&self) -> Vec<BackendServer> {
+        self.servers
+            .read()
+            .unwrap()
+            .servers
+            .iter()
+            .map(|(server, weight)| BackendServer {
+                server: server.clone(),
+                weight: Some(*weight),
+            })
+            .collect()
+    }
+
+    fn set_servers(&self, servers: Vec<BackendServer>) {
+        let servers = WeightedServers::new(servers);
+        log::info!(
+            "WeightedRoundRobinStrategy servers replaced with: {:?}, total_weight: {}",
+            servers.servers,
+            servers.total_weight
+        );
+        let mut current = self.servers.write().unwrap();
+        self.health
+            .set_servers(servers.servers.iter().map(|(server, _)| server));
+        *current = servers;
+    }
 }
@@ -1,19 +1,38 @@
-use config::load_config;
-use load_balancer::factory::{LoadBalancer, LoadBalancerFactory};
-use proxy_service::{proxy_bridge::ProxyBridge, proxy_handler::ProxyHandler};
-use server::server_manager::ServerManager;
-use std::sync::Arc;
-use types::Frontend;
-
-mod config;
-mod load_balancer;
-mod proxy_service;
-mod server;
-mod types;
+use oxidegate::{
+    config::load_config,
+    gateway::Gateway,
+    proxy_service::{access_log::AccessLogger, request_id},
+    server::{admin::AdminState, server_manager::ServerManager, shutdown::Shutdown},
+    telemetry::Tracer,
+};
+use std::{io::Write, sync::Arc, time::Duration};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    env_logger::init();
+    env_logger::Builder::from_default_env()
+        .format(|buf, record| {
+            let timestamp = buf.timestamp();
+            match request_id::current() {
+                Some(request_id) => writeln!(
+                    buf,
+                    "[{} {:<5} {} {}] {}",
+                    timestamp,
+                    record.level(),
+                    record.target(),
+                    request_id,
+                    record.args()
+                ),
+                None => writeln!(
+                    buf,
+                    "[{} {:<5} {}] {}",
+                    timestamp,
+                    record.level(),
+                    record.target(),
+                    record.args()
+                ),
+            }
+        })
+        .init();
 
     let config = match load_config().await {
         Ok(conf) => conf,
@@ -23,35 +42,43 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
-    let connections: Vec<(Frontend, Arc<dyn LoadBalancer>)> = config
-        .frontends
-        .iter()
-        .map(|frontend| {
-            let backend = config
-                .backends
-                .iter()
-                .find(|backend| backend.name == frontend.backend)
-                .unwrap();
-            let balancer =
-                LoadBalancerFactory::create(backend.lb_algorithm, backend.servers.clone());
-
-            (frontend.clone(), balancer)
-        })
-        .collect::<Vec<_>>();
+    let access_log = match &config.server.access_log {
+        Some(access_log) => {
+            let access_log =
+                AccessLogger::new(access_log).map_err(|e| format!("Invalid access_log: {}", e))?;
+            let access_log = Arc::new(access_log);
+            #[cfg(unix)]
+            access_log.clone().reopen_on_signal()?;
+            Some(access_log)
+        }
+        None => None,
+    };
+
+    let tracer = match &config.server.tracing {
+        Some(tracing) => Some(Tracer::new(tracing)?),
+        None => None,
+    };
+
+    let server_settings = config.server.clone();
+    let gateway = Arc::new(Gateway::new(config, access_log.clone(), tracer.clone())?);
+    gateway.watch(Duration::from_millis(
+        server_settings.config_watch_interval_ms,
+    ))?;
+
+    let shutdown = Shutdown::new();
+    let admin_state = Arc::new(AdminState::new(gateway.clone(), shutdown.clone()));
 
-    let proxy_handlers: Arc<Vec<(types::Frontend, Arc<ProxyHandler>)>> = Arc::new(
-        connections
-            .iter()
-            .map(|(frontend, balancer)| {
-                let handler = ProxyHandler::new(balancer.to_owned());
-                (frontend.clone(), Arc::new(handler))
-            })
-            .collect::<Vec<_>>(),
-    );
+    let server_manager = ServerManager::new(server_settings, gateway, admin_state, shutdown);
 
-    let proxy_bridge: Arc<ProxyBridge> = Arc::new(ProxyBridge::new(proxy_handlers));
+    server_manager.start_server().await?;
 
-    let server_manager = ServerManager::new(config.server, proxy_bridge);
+    if let Some(access_log) = access_log {
+        access_log.flush();
+    }
+    if let Some(tracer) = tracer {
+        tracer.flush().await;
+    }
 
-    server_manager.start_server().await
+    log::info!("Shutdown complete");
+    Ok(())
 }
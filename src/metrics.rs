@@ -0,0 +1,469 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Arc, LazyLock, Mutex,
+    },
+    time::Duration,
+};
+
+use hyper::{Method, StatusCode};
+
+use crate::{load_balancer::factory::LoadBalancer, proxy_service::grpc::Code};
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// gRPC methods tracked separately, later ones are grouped as `other`, since the service and
+/// method come from the request path.
+const MAX_GRPC_METHODS: usize = 1000;
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+/// The process wide metrics registry, rendered in the Prometheus text format by the admin
+/// listener.
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(self.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{},le=\"{}\"}} {}",
+                name, labels, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{},le=\"+Inf\"}} {}",
+            name, labels, self.count
+        );
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+}
+
+/// Labels of a proxied request.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RequestLabels {
+    pub frontend: String,
+    pub backend: String,
+    pub server: String,
+    pub method: String,
+    pub status_class: &'static str,
+}
+
+impl RequestLabels {
+    pub fn new(
+        frontend: Option<&str>,
+        backend: Option<&str>,
+        server: Option<&str>,
+        method: &Method,
+        status: StatusCode,
+    ) -> Self {
+        Self {
+            frontend: frontend.unwrap_or_default().to_string(),
+            backend: backend.unwrap_or_default().to_string(),
+            server: server.unwrap_or_default().to_string(),
+            method: method_label(method).to_string(),
+            status_class: status_class(status),
+        }
+    }
+
+    fn render(&self) -> String {
+        format!(
+            "frontend=\"{}\",backend=\"{}\",server=\"{}\",method=\"{}\",status=\"{}\"",
+            escape(&self.frontend),
+            escape(&self.backend),
+            escape(&self.server),
+            self.method,
+            self.status_class
+        )
+    }
+}
+
+/// Calls of a gRPC method by status code and their durations.
+#[derive(Default)]
+struct GrpcMethod {
+    calls: BTreeMap<&'static str, u64>,
+    duration: Histogram,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    requests: Mutex<BTreeMap<RequestLabels, (u64, Histogram)>>,
+    grpc: Mutex<BTreeMap<(String, String), GrpcMethod>>,
+    upstream_latency: Mutex<BTreeMap<(String, String), Histogram>>,
+    upstream_errors: Mutex<BTreeMap<(String, String, &'static str), u64>>,
+    timeouts: Mutex<BTreeMap<&'static str, u64>>,
+    accepted_connections: Mutex<BTreeMap<&'static str, u64>>,
+    in_flight: AtomicI64,
+    tunnels: AtomicI64,
+    tls_handshake_failures: AtomicU64,
+    balancers: Mutex<Vec<(String, Arc<dyn LoadBalancer>)>>,
+}
+
+/// Counts a request as in flight until it is dropped.
+pub struct InFlight;
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        metrics().in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Counts an upgraded connection as open until it is dropped.
+pub struct OpenTunnel;
+
+impl Drop for OpenTunnel {
+    fn drop(&mut self) {
+        metrics().tunnels.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Metrics {
+    pub fn request_started(&self) -> InFlight {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlight
+    }
+
+    pub fn tunnel_opened(&self) -> OpenTunnel {
+        self.tunnels.fetch_add(1, Ordering::Relaxed);
+        OpenTunnel
+    }
+
+    pub fn observe_request(&self, labels: RequestLabels, duration: Duration) {
+        if let Ok(mut requests) = self.requests.lock() {
+            let (count, histogram) = requests.entry(labels).or_default();
+            *count += 1;
+            histogram.observe(duration);
+        }
+    }
+
+    /// Records a completed gRPC call of `service`/`method`.
+    pub fn observe_grpc(&self, service: &str, method: &str, code: Code, duration: Duration) {
+        if let Ok(mut grpc) = self.grpc.lock() {
+            let mut key = (service.to_string(), method.to_string());
+            if grpc.len() >= MAX_GRPC_METHODS && !grpc.contains_key(&key) {
+                key = ("other".to_string(), "other".to_string());
+            }
+
+            let grpc_method = grpc.entry(key).or_default();
+            *grpc_method.calls.entry(code.name()).or_default() += 1;
+            grpc_method.duration.observe(duration);
+        }
+    }
+
+    pub fn observe_upstream(&self, backend: &str, server: &str, duration: Duration) {
+        if let Ok(mut upstream_latency) = self.upstream_latency.lock() {
+            upstream_latency
+                .entry((backend.to_string(), server.to_string()))
+                .or_default()
+                .observe(duration);
+        }
+    }
+
+    /// Counts a failed upstream request. `kind` is `connect`, `request` or `timeout`.
+    pub fn upstream_error(&self, backend: &str, server: &str, kind: &'static str) {
+        if let Ok(mut upstream_errors) = self.upstream_errors.lock() {
+            *upstream_errors
+                .entry((backend.to_string(), server.to_string(), kind))
+                .or_default() += 1;
+        }
+    }
+
+    /// Counts a timeout. `kind` is `request` for upstream responses, `body_idle` for stalled
+    /// bodies or `tunnel_idle` for upgraded connections.
+    pub fn timeout(&self, kind: &'static str) {
+        increment(&self.timeouts, kind);
+    }
+
+    pub fn connection_accepted(&self, listener: &'static str) {
+        increment(&self.accepted_connections, listener);
+    }
+
+    pub fn tls_handshake_failed(&self) {
+        self.tls_handshake_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Sets the balancers whose active connections are reported.
+    pub fn set_balancers(&self, balancers: Vec<(String, Arc<dyn LoadBalancer>)>) {
+        if let Ok(mut current) = self.balancers.lock() {
+            *current = balancers;
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "oxidegate_requests_total",
+            "counter",
+            "Proxied requests.",
+        );
+        if let Ok(requests) = self.requests.lock() {
+            for (labels, (count, _)) in requests.iter() {
+                let _ = writeln!(
+                    out,
+                    "oxidegate_requests_total{{{}}} {}",
+                    labels.render(),
+                    count
+                );
+            }
+        }
+
+        header(
+            &mut out,
+            "oxidegate_request_duration_seconds",
+            "histogram",
+            "Time until the response headers were sent to the client.",
+        );
+        if let Ok(requests) = self.requests.lock() {
+            for (labels, (_, histogram)) in requests.iter() {
+                histogram.render(
+                    &mut out,
+                    "oxidegate_request_duration_seconds",
+                    &labels.render(),
+                );
+            }
+        }
+
+        header(
+            &mut out,
+            "oxidegate_grpc_requests_total",
+            "counter",
+            "gRPC calls by method and status code.",
+        );
+        if let Ok(grpc) = self.grpc.lock() {
+            for ((service, method), grpc_method) in grpc.iter() {
+                for (code, count) in &grpc_method.calls {
+                    let _ = writeln!(
+                        out,
+                        "oxidegate_grpc_requests_total{{service=\"{}\",method=\"{}\",code=\"{}\"}} {}",
+                        escape(service),
+                        escape(method),
+                        code,
+                        count
+                    );
+                }
+            }
+        }
+
+        header(
+            &mut out,
+            "oxidegate_grpc_request_duration_seconds",
+            "histogram",
+            "Time until a gRPC call completed, including the response stream.",
+        );
+        if let Ok(grpc) = self.grpc.lock() {
+            for ((service, method), grpc_method) in grpc.iter() {
+                grpc_method.duration.render(
+                    &mut out,
+                    "oxidegate_grpc_request_duration_seconds",
+                    &format!(
+                        "service=\"{}\",method=\"{}\"",
+                        escape(service),
+                        escape(method)
+                    ),
+                );
+            }
+        }
+
+        header(
+            &mut out,
+            "oxidegate_upstream_duration_seconds",
+            "histogram",
+            "Time until an upstream server returned the response headers.",
+        );
+        if let Ok(upstream_latency) = self.upstream_latency.lock() {
+            for ((backend, server), histogram) in upstream_latency.iter() {
+                histogram.render(
+                    &mut out,
+                    "oxidegate_upstream_duration_seconds",
+                    &format!(
+                        "backend=\"{}\",server=\"{}\"",
+                        escape(backend),
+                        escape(server)
+                    ),
+                );
+            }
+        }
+
+        header(
+            &mut out,
+            "oxidegate_requests_in_flight",
+            "gauge",
+            "Requests currently being handled.",
+        );
+        let _ = writeln!(
+            out,
+            "oxidegate_requests_in_flight {}",
+            self.in_flight.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut out,
+            "oxidegate_upgraded_connections",
+            "gauge",
+            "Upgraded connections, such as WebSockets, currently tunnelled to a server.",
+        );
+        let _ = writeln!(
+            out,
+            "oxidegate_upgraded_connections {}",
+            self.tunnels.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut out,
+            "oxidegate_upstream_active_connections",
+            "gauge",
+            "Requests currently sent to an upstream server, as tracked by the load balancer.",
+        );
+        if let Ok(balancers) = self.balancers.lock() {
+            for (backend, balancer) in balancers.iter() {
+                for (server, connections) in balancer.connections() {
+                    let _ = writeln!(
+                        out,
+                        "oxidegate_upstream_active_connections{{backend=\"{}\",server=\"{}\"}} {}",
+                        escape(backend),
+                        escape(&server),
+                        connections
+                    );
+                }
+            }
+        }
+
+        header(
+            &mut out,
+            "oxidegate_upstream_errors_total",
+            "counter",
+            "Failed upstream requests by kind.",
+        );
+        if let Ok(upstream_errors) = self.upstream_errors.lock() {
+            for ((backend, server, kind), count) in upstream_errors.iter() {
+                let _ = writeln!(
+                    out,
+                    "oxidegate_upstream_errors_total{{backend=\"{}\",server=\"{}\",kind=\"{}\"}} {}",
+                    escape(backend),
+                    escape(server),
+                    kind,
+                    count
+                );
+            }
+        }
+
+        header(
+            &mut out,
+            "oxidegate_timeouts_total",
+            "counter",
+            "Timeouts by kind.",
+        );
+        if let Ok(timeouts) = self.timeouts.lock() {
+            for (kind, count) in timeouts.iter() {
+                let _ = writeln!(
+                    out,
+                    "oxidegate_timeouts_total{{kind=\"{}\"}} {}",
+                    kind, count
+                );
+            }
+        }
+
+        header(
+            &mut out,
+            "oxidegate_tls_handshake_failures_total",
+            "counter",
+            "Failed TLS handshakes.",
+        );
+        let _ = writeln!(
+            out,
+            "oxidegate_tls_handshake_failures_total {}",
+            self.tls_handshake_failures.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut out,
+            "oxidegate_connections_accepted_total",
+            "counter",
+            "Accepted client connections by listener.",
+        );
+        if let Ok(accepted_connections) = self.accepted_connections.lock() {
+            for (listener, count) in accepted_connections.iter() {
+                let _ = writeln!(
+                    out,
+                    "oxidegate_connections_accepted_total{{listener=\"{}\"}} {}",
+                    listener, count
+                );
+            }
+        }
+
+        out
+    }
+}
+
+fn increment(counters: &Mutex<BTreeMap<&'static str, u64>>, key: &'static str) {
+    if let Ok(mut counters) = counters.lock() {
+        *counters.entry(key).or_default() += 1;
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Keeps the label cardinality bounded by grouping unknown methods.
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::PATCH => "PATCH",
+        Method::OPTIONS => "OPTIONS",
+        Method::CONNECT => "CONNECT",
+        Method::TRACE => "TRACE",
+        _ => "OTHER",
+    }
+}
+
+fn status_class(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        100..=199 => "1xx",
+        200..=299 => "2xx",
+        300..=399 => "3xx",
+        400..=499 => "4xx",
+        _ => "5xx",
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
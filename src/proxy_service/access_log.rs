@@ -0,0 +1,494 @@
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, LineWriter, Write},
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, SyncSender, TrySendError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use hyper::{
+    header::{HOST, REFERER, USER_AGENT},
+    Method, Request, StatusCode, Version,
+};
+
+use crate::types::{AccessLog, AccessLogFormat};
+
+use super::{
+    context::RequestStats,
+    gateway_body::ByteCounter,
+    template::{self, Part},
+};
+
+#[derive(Debug)]
+pub struct AccessLogError(String);
+
+impl fmt::Display for AccessLogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for AccessLogError {}
+
+#[derive(Clone, Copy)]
+enum Field {
+    Time,
+    RequestId,
+    ClientIp,
+    Method,
+    Uri,
+    Protocol,
+    Host,
+    Status,
+    BytesIn,
+    BytesOut,
+    Frontend,
+    Server,
+    UpstreamLatency,
+    TotalLatency,
+    Referer,
+    UserAgent,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "time" => Field::Time,
+            "request_id" => Field::RequestId,
+            "client_ip" => Field::ClientIp,
+            "method" => Field::Method,
+            "uri" => Field::Uri,
+            "protocol" => Field::Protocol,
+            "host" => Field::Host,
+            "status" => Field::Status,
+            "bytes_in" => Field::BytesIn,
+            "bytes_out" => Field::BytesOut,
+            "frontend" => Field::Frontend,
+            "server" => Field::Server,
+            "upstream_latency_ms" => Field::UpstreamLatency,
+            "total_latency_ms" => Field::TotalLatency,
+            "referer" => Field::Referer,
+            "user_agent" => Field::UserAgent,
+            _ => return None,
+        })
+    }
+}
+
+enum Format {
+    Common,
+    Combined,
+    Json,
+    Custom(Vec<Part<Field>>),
+}
+
+/// Entries waiting for the writer thread, beyond which new entries are dropped.
+const QUEUE_SIZE: usize = 8192;
+
+enum Output {
+    Stdout,
+    File {
+        path: String,
+        file: LineWriter<File>,
+    },
+}
+
+impl Output {
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        match self {
+            Output::Stdout => io::stdout().lock().write_all(line.as_bytes()),
+            Output::File { file, .. } => file.write_all(line.as_bytes()),
+        }
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        if let Output::File { path, file } = self {
+            *file = open(path)?;
+            log::info!("Reopened access log {:?}", path);
+        }
+        Ok(())
+    }
+}
+
+enum Command {
+    Write(String),
+    Reopen,
+    /// Answers once every entry queued before it is written.
+    Flush(mpsc::Sender<()>),
+}
+
+/// Writes one line per proxied request, separately from the diagnostic log. Lines are written
+/// by a dedicated thread, so a slow disk never blocks the requests.
+pub struct AccessLogger {
+    format: Format,
+    sender: SyncSender<Command>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl AccessLogger {
+    pub fn new(config: &AccessLog) -> Result<Self, AccessLogError> {
+        let format = match config.format {
+            AccessLogFormat::Common => Format::Common,
+            AccessLogFormat::Combined => Format::Combined,
+            AccessLogFormat::Json => Format::Json,
+            AccessLogFormat::Custom => {
+                let template = config.template.as_deref().ok_or_else(|| {
+                    AccessLogError("a template is required for the Custom format".to_string())
+                })?;
+                Format::Custom(
+                    template::parse(template, Field::parse)
+                        .map_err(|e| AccessLogError(e.to_string()))?,
+                )
+            }
+        };
+
+        let output = match &config.path {
+            Some(path) => Output::File {
+                path: path.clone(),
+                file: open(path)
+                    .map_err(|e| AccessLogError(format!("failed to open {:?}: {}", path, e)))?,
+            },
+            None => Output::Stdout,
+        };
+
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_SIZE);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let writer_dropped = dropped.clone();
+
+        thread::Builder::new()
+            .name("access-log".to_string())
+            .spawn(move || write_entries(output, receiver, writer_dropped))
+            .map_err(|e| AccessLogError(format!("failed to start the writer: {}", e)))?;
+
+        Ok(Self {
+            format,
+            sender,
+            dropped,
+        })
+    }
+
+    /// Reopens the log file, e.g. after it was moved by logrotate.
+    pub fn reopen(&self) {
+        if let Err(TrySendError::Full(_)) = self.sender.try_send(Command::Reopen) {
+            log::warn!("Access log queue is full, not reopening the access log");
+        }
+    }
+
+    /// Waits until the entries logged so far are written.
+    pub fn flush(&self) {
+        let (done, wait) = mpsc::channel();
+        if self.sender.send(Command::Flush(done)).is_ok() {
+            let _ = wait.recv();
+        }
+    }
+
+    /// Reopens the log file whenever the process receives `SIGUSR1`.
+    #[cfg(unix)]
+    pub fn reopen_on_signal(self: Arc<Self>) -> io::Result<tokio::task::JoinHandle<()>> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut signal = signal(SignalKind::user_defined1())?;
+
+        Ok(tokio::spawn(async move {
+            while signal.recv().await.is_some() {
+                self.reopen();
+            }
+        }))
+    }
+
+    fn write(&self, entry: &AccessEntry) {
+        let mut line = self.format(entry);
+        line.push('\n');
+
+        if let Err(TrySendError::Full(_)) = self.sender.try_send(Command::Write(line)) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn format(&self, entry: &AccessEntry) -> String {
+        match &self.format {
+            Format::Common => format!("{}{}", common(entry), extras(entry)),
+            Format::Combined => format!(
+                "{} \"{}\" \"{}\"{}",
+                common(entry),
+                escape(entry.referer.as_deref().unwrap_or("-")),
+                escape(entry.user_agent.as_deref().unwrap_or("-")),
+                extras(entry)
+            ),
+            Format::Json => json(entry),
+            Format::Custom(parts) => parts
+                .iter()
+                .map(|part| match part {
+                    Part::Literal(literal) => literal.clone(),
+                    Part::Placeholder(field) => {
+                        entry.field(*field).unwrap_or_else(|| "-".to_string())
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A request that is being served. The entry is written when it is dropped, which happens when
+/// the response body has been sent or the client went away.
+pub struct AccessEntry {
+    logger: Arc<AccessLogger>,
+    time: SystemTime,
+    start: Instant,
+    request_id: String,
+    client_ip: IpAddr,
+    method: Method,
+    uri: String,
+    version: Version,
+    host: Option<String>,
+    referer: Option<String>,
+    user_agent: Option<String>,
+    status: StatusCode,
+    stats: Arc<RequestStats>,
+    bytes_out: AtomicU64,
+}
+
+impl AccessEntry {
+    /// Captures the parts of `req` needed by the log before it is handed to a `ProxyHandler`.
+    pub fn start<B>(
+        logger: Arc<AccessLogger>,
+        req: &Request<B>,
+        client_ip: IpAddr,
+        request_id: String,
+        stats: Arc<RequestStats>,
+    ) -> Self {
+        let header = |name| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+
+        Self {
+            logger,
+            time: SystemTime::now(),
+            start: Instant::now(),
+            request_id,
+            client_ip,
+            method: req.method().clone(),
+            uri: req.uri().to_string(),
+            version: req.version(),
+            host: req
+                .uri()
+                .authority()
+                .map(|authority| authority.to_string())
+                .or_else(|| header(HOST)),
+            referer: header(REFERER),
+            user_agent: header(USER_AGENT),
+            status: StatusCode::OK,
+            stats,
+            bytes_out: AtomicU64::new(0),
+        }
+    }
+
+    pub fn set_status(&mut self, status: StatusCode) {
+        self.status = status;
+    }
+
+    fn field(&self, field: Field) -> Option<String> {
+        let upstream = self.stats.upstream();
+
+        match field {
+            Field::Time => Some(rfc3339(self.time)),
+            Field::RequestId => Some(self.request_id.clone()),
+            Field::ClientIp => Some(self.client_ip.to_string()),
+            Field::Method => Some(self.method.to_string()),
+            Field::Uri => Some(self.uri.clone()),
+            Field::Protocol => Some(format!("{:?}", self.version)),
+            Field::Host => self.host.clone(),
+            Field::Status => Some(self.status.as_u16().to_string()),
+            Field::BytesIn => Some(self.stats.bytes_in.load(Ordering::Relaxed).to_string()),
+            Field::BytesOut => Some(self.bytes_out.load(Ordering::Relaxed).to_string()),
+            Field::Frontend => self.stats.frontend(),
+            Field::Server => upstream.map(|(server, _)| server),
+            Field::UpstreamLatency => upstream.map(|(_, latency)| millis(latency)),
+            Field::TotalLatency => Some(millis(self.start.elapsed())),
+            Field::Referer => self.referer.clone(),
+            Field::UserAgent => self.user_agent.clone(),
+        }
+    }
+}
+
+impl ByteCounter for AccessEntry {
+    fn add(&self, bytes: usize) {
+        self.bytes_out.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+impl Drop for AccessEntry {
+    fn drop(&mut self) {
+        self.logger.write(self);
+    }
+}
+
+/// Runs on the writer thread until the logger is dropped.
+fn write_entries(mut output: Output, receiver: mpsc::Receiver<Command>, dropped: Arc<AtomicU64>) {
+    for command in receiver {
+        match command {
+            Command::Write(line) => {
+                if let Err(e) = output.write(&line) {
+                    log::error!("Failed to write access log: {}", e);
+                }
+            }
+            Command::Reopen => {
+                if let Err(e) = output.reopen() {
+                    log::error!("Failed to reopen access log: {}", e);
+                }
+            }
+            Command::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+
+        match dropped.swap(0, Ordering::Relaxed) {
+            0 => {}
+            count => log::warn!(
+                "Dropped {} access log entries, the writer fell behind",
+                count
+            ),
+        }
+    }
+}
+
+fn open(path: &str) -> io::Result<LineWriter<File>> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    Ok(LineWriter::new(file))
+}
+
+/// `host ident authuser [date] "request" status bytes`
+fn common(entry: &AccessEntry) -> String {
+    format!(
+        "{} - - [{}] \"{} {} {:?}\" {} {}",
+        entry.client_ip,
+        clf_time(entry.time),
+        entry.method,
+        escape(&entry.uri),
+        entry.version,
+        entry.status.as_u16(),
+        match entry.bytes_out.load(Ordering::Relaxed) {
+            0 => "-".to_string(),
+            bytes => bytes.to_string(),
+        }
+    )
+}
+
+/// Fields appended to the Common and Combined formats.
+fn extras(entry: &AccessEntry) -> String {
+    let field = |field| entry.field(field).unwrap_or_else(|| "-".to_string());
+
+    format!(
+        " request_id={} frontend=\"{}\" server=\"{}\" bytes_in={} upstream_ms={} total_ms={}",
+        field(Field::RequestId),
+        escape(&field(Field::Frontend)),
+        escape(&field(Field::Server)),
+        field(Field::BytesIn),
+        field(Field::UpstreamLatency),
+        field(Field::TotalLatency),
+    )
+}
+
+fn json(entry: &AccessEntry) -> String {
+    let upstream = entry.stats.upstream();
+
+    serde_json::json!({
+        "time": rfc3339(entry.time),
+        "request_id": entry.request_id,
+        "client_ip": entry.client_ip.to_string(),
+        "method": entry.method.as_str(),
+        "uri": entry.uri,
+        "protocol": format!("{:?}", entry.version),
+        "host": entry.host,
+        "status": entry.status.as_u16(),
+        "bytes_in": entry.stats.bytes_in.load(Ordering::Relaxed),
+        "bytes_out": entry.bytes_out.load(Ordering::Relaxed),
+        "frontend": entry.stats.frontend(),
+        "server": upstream.as_ref().map(|(server, _)| server),
+        "upstream_latency_ms": upstream.map(|(_, latency)| latency.as_secs_f64() * 1000.0),
+        "total_latency_ms": entry.start.elapsed().as_secs_f64() * 1000.0,
+        "referer": entry.referer,
+        "user_agent": entry.user_agent,
+    })
+    .to_string()
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64() * 1000.0)
+}
+
+/// Escapes quotes, backslashes and control characters of a quoted log field.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// `10/Oct/2000:13:55:36 +0000`
+fn clf_time(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second, _) = utc(time);
+    format!(
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        hour,
+        minute,
+        second
+    )
+}
+
+/// `2000-10-10T13:55:36.123Z`
+fn rfc3339(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second, millis) = utc(time);
+    format!(
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year, month, day, hour, minute, second, millis
+    )
+}
+
+/// Splits a time into UTC calendar fields, using the days-to-civil algorithm of Howard Hinnant.
+fn utc(time: SystemTime) -> (i64, u32, u32, u32, u32, u32, u32) {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs() as i64;
+    let (days, secs_of_day) = (secs.div_euclid(86400), secs.rem_euclid(86400) as u32);
+
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis(),
+    )
+}
@@ -0,0 +1,105 @@
+use std::fmt;
+
+use hyper::{
+    http::uri::{Authority, Scheme},
+    Uri,
+};
+
+use crate::types::UpstreamProtocol;
+
+/// A parsed `BackendServer.server`, e.g. `http://host:8080/v2`.
+#[derive(Debug, Clone)]
+pub struct BackendUrl {
+    scheme: Scheme,
+    authority: Authority,
+    base_path: String,
+}
+
+#[derive(Debug)]
+pub enum BackendUrlError {
+    Invalid(hyper::http::uri::InvalidUri),
+    MissingScheme,
+    UnsupportedScheme(String),
+    MissingAuthority,
+    HasQuery,
+    ProtocolMismatch(UpstreamProtocol),
+}
+
+impl fmt::Display for BackendUrlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendUrlError::Invalid(e) => write!(f, "{}", e),
+            BackendUrlError::MissingScheme => write!(f, "missing scheme, expected http or https"),
+            BackendUrlError::UnsupportedScheme(scheme) => {
+                write!(f, "unsupported scheme {}, expected http or https", scheme)
+            }
+            BackendUrlError::MissingAuthority => write!(f, "missing host"),
+            BackendUrlError::HasQuery => write!(f, "query strings are not supported"),
+            BackendUrlError::ProtocolMismatch(UpstreamProtocol::H2c) => {
+                write!(f, "protocol H2c requires an http server")
+            }
+            BackendUrlError::ProtocolMismatch(protocol) => {
+                write!(f, "protocol {:?} requires an https server", protocol)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BackendUrlError {}
+
+impl BackendUrl {
+    pub fn parse(server: &str) -> Result<Self, BackendUrlError> {
+        let uri: Uri = server.parse().map_err(BackendUrlError::Invalid)?;
+
+        let scheme = uri.scheme().ok_or(BackendUrlError::MissingScheme)?.clone();
+        if scheme != Scheme::HTTP && scheme != Scheme::HTTPS {
+            return Err(BackendUrlError::UnsupportedScheme(scheme.to_string()));
+        }
+
+        let authority = uri
+            .authority()
+            .ok_or(BackendUrlError::MissingAuthority)?
+            .clone();
+
+        if uri.query().is_some() {
+            return Err(BackendUrlError::HasQuery);
+        }
+
+        Ok(Self {
+            scheme,
+            authority,
+            base_path: uri.path().trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// Rejects schemes the protocol cannot be spoken over: `Http2` needs TLS for ALPN and `H2c`
+    /// is cleartext only.
+    pub fn check_protocol(&self, protocol: UpstreamProtocol) -> Result<(), BackendUrlError> {
+        let compatible = match protocol {
+            UpstreamProtocol::Http2 => self.scheme == Scheme::HTTPS,
+            UpstreamProtocol::H2c => self.scheme == Scheme::HTTP,
+            UpstreamProtocol::Http1 | UpstreamProtocol::Auto => true,
+        };
+
+        if compatible {
+            Ok(())
+        } else {
+            Err(BackendUrlError::ProtocolMismatch(protocol))
+        }
+    }
+
+    /// Joins the base path of the backend with the path of the request.
+    pub fn join(&self, path: &str, query: Option<&str>) -> Result<Uri, hyper::http::Error> {
+        let path = path.strip_prefix('/').unwrap_or(path);
+        let path_and_query = match query {
+            Some(query) => format!("{}/{}?{}", self.base_path, path, query),
+            None => format!("{}/{}", self.base_path, path),
+        };
+
+        Uri::builder()
+            .scheme(self.scheme.clone())
+            .authority(self.authority.clone())
+            .path_and_query(path_and_query)
+            .build()
+    }
+}
@@ -0,0 +1,57 @@
+use std::{
+    net::IpAddr,
+    sync::{atomic::AtomicU64, Arc, Mutex},
+    time::Duration,
+};
+
+use crate::telemetry::ParentSpan;
+
+/// Information about a request gathered by the `ProxyBridge` before it is handed to a
+/// `ProxyHandler`.
+pub struct RequestContext {
+    /// Address of the client, resolved through `X-Forwarded-For` when the peer is a trusted
+    /// proxy.
+    pub client_ip: IpAddr,
+    /// Id of the request, also sent upstream and echoed in the `X-Request-Id` header.
+    pub request_id: String,
+    pub stats: Arc<RequestStats>,
+    /// Server span of the request, when tracing is enabled.
+    pub trace: Option<ParentSpan>,
+}
+
+/// What happened to a request while it was proxied, filled in as it goes.
+#[derive(Default)]
+pub struct RequestStats {
+    /// Bytes of the request body sent upstream.
+    pub bytes_in: Arc<AtomicU64>,
+    route: Mutex<Option<(Option<String>, String)>>,
+    upstream: Mutex<Option<(String, Duration)>>,
+}
+
+impl RequestStats {
+    /// Records the name of the matched frontend and its backend.
+    pub fn set_route(&self, frontend: Option<String>, backend: &str) {
+        if let Ok(mut route) = self.route.lock() {
+            *route = Some((frontend, backend.to_string()));
+        }
+    }
+
+    pub fn frontend(&self) -> Option<String> {
+        self.route.lock().ok()?.as_ref()?.0.clone()
+    }
+
+    pub fn backend(&self) -> Option<String> {
+        Some(self.route.lock().ok()?.as_ref()?.1.clone())
+    }
+
+    /// Records the server of the last upstream attempt and how long it took to respond.
+    pub fn record_upstream(&self, server: &str, latency: Duration) {
+        if let Ok(mut upstream) = self.upstream.lock() {
+            *upstream = Some((server.to_string(), latency));
+        }
+    }
+
+    pub fn upstream(&self) -> Option<(String, Duration)> {
+        self.upstream.lock().ok()?.clone()
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+use hyper::{
+    header::{HeaderName, HeaderValue, FORWARDED},
+    HeaderMap,
+};
+
+use crate::types::{ForwardedHeaders, ServerSettings};
+
+use super::headers::append;
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+
+/// An IP network in CIDR notation, e.g. `10.0.0.0/8`. A bare address is a single host network.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+#[derive(Debug)]
+pub struct CidrError(String);
+
+impl fmt::Display for CidrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for CidrError {}
+
+impl FromStr for Cidr {
+    type Err = CidrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s, None),
+        };
+
+        let network = address
+            .parse::<IpAddr>()
+            .map_err(|_| CidrError(format!("invalid address {:?}", address)))?;
+
+        let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= max_prefix)
+                .ok_or_else(|| CidrError(format!("invalid prefix length {:?}", prefix)))?,
+            None => max_prefix,
+        };
+
+        Ok(Self { network, prefix })
+    }
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Adds the `X-Forwarded-*` and/or `Forwarded` headers to proxied requests. Values sent by a
+/// trusted proxy are extended, values sent by anyone else are replaced.
+pub struct Forwarder {
+    mode: ForwardedHeaders,
+    trusted_proxies: Vec<Cidr>,
+    proto: &'static str,
+}
+
+impl Forwarder {
+    pub fn new(settings: &ServerSettings) -> Result<Self, CidrError> {
+        let trusted_proxies = settings
+            .trusted_proxies
+            .iter()
+            .map(|cidr| cidr.parse())
+            .collect::<Result<Vec<Cidr>, _>>()?;
+
+        Ok(Self {
+            mode: settings.forwarded_headers,
+            trusted_proxies,
+            proto: if settings.enable_https {
+                "https"
+            } else {
+                "http"
+            },
+        })
+    }
+
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    /// Address of the original client: the peer itself, or the right-most untrusted entry of
+    /// `X-Forwarded-For` when the peer is a trusted proxy.
+    pub fn client_ip(&self, headers: &HeaderMap, peer: SocketAddr) -> IpAddr {
+        let mut client = peer.ip().to_canonical();
+        if !self.is_trusted(client) {
+            return client;
+        }
+
+        let forwarded_for = headers
+            .get_all(X_FORWARDED_FOR)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect::<Vec<_>>();
+
+        for address in forwarded_for.into_iter().rev() {
+            let Ok(address) = address.trim().parse::<IpAddr>() else {
+                break;
+            };
+
+            client = address.to_canonical();
+            if !self.is_trusted(client) {
+                break;
+            }
+        }
+
+        client
+    }
+
+    pub fn apply(&self, headers: &mut HeaderMap, host: Option<&str>, peer: SocketAddr) {
+        let trusted = self.is_trusted(peer.ip());
+        let client = peer.ip().to_canonical();
+
+        if !trusted {
+            for name in [
+                X_FORWARDED_FOR,
+                X_FORWARDED_PROTO,
+                X_FORWARDED_HOST,
+                FORWARDED,
+            ] {
+                headers.remove(name);
+            }
+        }
+
+        if matches!(
+            self.mode,
+            ForwardedHeaders::XForwarded | ForwardedHeaders::Both
+        ) {
+            append(headers, X_FORWARDED_FOR, &client.to_string());
+
+            if !headers.contains_key(X_FORWARDED_PROTO) {
+                headers.insert(X_FORWARDED_PROTO, HeaderValue::from_static(self.proto));
+            }
+
+            if let Some(host) = host.and_then(|host| HeaderValue::from_str(host).ok()) {
+                headers.entry(X_FORWARDED_HOST).or_insert(host);
+            }
+        }
+
+        if matches!(
+            self.mode,
+            ForwardedHeaders::Forwarded | ForwardedHeaders::Both
+        ) {
+            let node = match client {
+                IpAddr::V4(ip) => ip.to_string(),
+                IpAddr::V6(ip) => format!("\"[{}]\"", ip),
+            };
+
+            let mut element = format!("for={};proto={}", node, self.proto);
+            if let Some(host) = host {
+                element.push_str(";host=");
+                element.push_str(&quote(host));
+            }
+
+            append(headers, FORWARDED, &element);
+        }
+    }
+}
+
+/// Quotes a `Forwarded` parameter value unless it is a valid token.
+fn quote(value: &str) -> String {
+    let is_token = value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c));
+
+    if is_token {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
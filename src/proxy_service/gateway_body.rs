@@ -1,26 +1,204 @@
-use std::{pin::Pin, task::Poll};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::Poll,
+    time::Duration,
+};
+
+use crate::metrics::metrics;
 
+use super::{
+    grpc::{Code, GRPC_MESSAGE, GRPC_STATUS},
+    request_id,
+};
+use http_body_util::Full;
 use hyper::{
-    body::{Body, Bytes, Incoming},
-    Error,
+    body::{Body, Bytes, Frame, Incoming},
+    header::HeaderValue,
+    HeaderMap,
 };
+use tokio::time::{sleep, sleep_until, Instant, Sleep};
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Observes the frames of a `GatewayBody::Counted` body.
+pub trait ByteCounter: Send + Sync {
+    /// Receives the size of every data frame.
+    fn add(&self, bytes: usize);
+
+    /// Receives the trailers ending the body, if any.
+    fn trailers(&self, _trailers: &HeaderMap) {}
+}
+
+impl ByteCounter for AtomicU64 {
+    fn add(&self, bytes: usize) {
+        self.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
 
 pub enum GatewayBody {
     Incomming(Incoming),
+    IdleTimeout {
+        body: Incoming,
+        timeout: Duration,
+        sleep: Pin<Box<Sleep>>,
+    },
+    Buffered(Full<Bytes>),
+    Counted {
+        body: Box<GatewayBody>,
+        counter: Arc<dyn ByteCounter>,
+    },
+    /// Logs emitted while `body` is polled carry `request_id`.
+    Scoped {
+        body: Box<GatewayBody>,
+        request_id: Arc<str>,
+    },
+    /// Data already read from `body`, sent before the rest of it.
+    Prefixed {
+        prefix: Option<Bytes>,
+        body: Box<GatewayBody>,
+    },
+    /// Ends `body` at `sleep`, with `DEADLINE_EXCEEDED` trailers when `grpc_trailers` is set and
+    /// with an error otherwise.
+    Deadline {
+        body: Box<GatewayBody>,
+        sleep: Pin<Box<Sleep>>,
+        grpc_trailers: bool,
+    },
+    /// A body without data, ending with trailer fields.
+    Trailers(Option<HeaderMap>),
     Empty,
 }
 
+impl GatewayBody {
+    /// Wraps a streamed body, failing it when no frame arrives within `idle_timeout`.
+    pub fn streaming(body: Incoming, idle_timeout: Option<Duration>) -> Self {
+        match idle_timeout {
+            Some(timeout) => GatewayBody::IdleTimeout {
+                body,
+                timeout,
+                sleep: Box::pin(sleep(timeout)),
+            },
+            None => GatewayBody::Incomming(body),
+        }
+    }
+
+    /// Reports the bytes passing through the body to `counter`, which is dropped with the body.
+    pub fn counted(self, counter: Arc<dyn ByteCounter>) -> Self {
+        GatewayBody::Counted {
+            body: Box::new(self),
+            counter,
+        }
+    }
+
+    /// Ends the body at the deadline of a gRPC call.
+    pub fn with_deadline(self, deadline: Instant, grpc_trailers: bool) -> Self {
+        GatewayBody::Deadline {
+            body: Box::new(self),
+            sleep: Box::pin(sleep_until(deadline)),
+            grpc_trailers,
+        }
+    }
+
+    /// Polls the body with `request_id` as the request id of its logs, as bodies are polled by
+    /// the connection rather than the task handling the request.
+    pub fn scoped(self, request_id: &str) -> Self {
+        GatewayBody::Scoped {
+            body: Box::new(self),
+            request_id: Arc::from(request_id),
+        }
+    }
+}
+
 impl Body for GatewayBody {
     type Data = Bytes;
 
-    type Error = Error;
+    type Error = BoxError;
 
     fn poll_frame(
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<Result<hyper::body::Frame<Self::Data>, Self::Error>>> {
+    ) -> std::task::Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
         match &mut *self.get_mut() {
-            GatewayBody::Incomming(incoming) => Pin::new(incoming).poll_frame(cx),
+            GatewayBody::Incomming(incoming) => {
+                Pin::new(incoming).poll_frame(cx).map_err(Into::into)
+            }
+            GatewayBody::IdleTimeout {
+                body,
+                timeout,
+                sleep,
+            } => match Pin::new(body).poll_frame(cx) {
+                Poll::Ready(frame) => {
+                    sleep.as_mut().reset(Instant::now() + *timeout);
+                    Poll::Ready(frame.map(|frame| frame.map_err(Into::into)))
+                }
+                Poll::Pending => match sleep.as_mut().poll(cx) {
+                    Poll::Ready(()) => {
+                        log::warn!("Body idle for longer than {:?}, aborting", timeout);
+                        metrics().timeout("body_idle");
+                        Poll::Ready(Some(Err("body idle timeout".into())))
+                    }
+                    Poll::Pending => Poll::Pending,
+                },
+            },
+            GatewayBody::Buffered(full) => Pin::new(full)
+                .poll_frame(cx)
+                .map_err(|never| match never {}),
+            GatewayBody::Counted { body, counter } => {
+                let frame = Pin::new(body.as_mut()).poll_frame(cx);
+                if let Poll::Ready(Some(Ok(frame))) = &frame {
+                    if let Some(data) = frame.data_ref() {
+                        counter.add(data.len());
+                    } else if let Some(trailers) = frame.trailers_ref() {
+                        counter.trailers(trailers);
+                    }
+                }
+                frame
+            }
+            GatewayBody::Scoped { body, request_id } => {
+                request_id::sync_scope(request_id, || Pin::new(body.as_mut()).poll_frame(cx))
+            }
+            GatewayBody::Prefixed { prefix, body } => match prefix.take() {
+                Some(data) => Poll::Ready(Some(Ok(Frame::data(data)))),
+                None => Pin::new(body.as_mut()).poll_frame(cx),
+            },
+            GatewayBody::Deadline {
+                body,
+                sleep,
+                grpc_trailers,
+            } => match Pin::new(body.as_mut()).poll_frame(cx) {
+                Poll::Ready(frame) => Poll::Ready(frame),
+                Poll::Pending => match sleep.as_mut().poll(cx) {
+                    Poll::Ready(()) => {
+                        log::warn!("gRPC deadline exceeded while streaming the body, aborting");
+                        metrics().timeout("grpc_deadline");
+
+                        if !*grpc_trailers {
+                            return Poll::Ready(Some(Err("gRPC deadline exceeded".into())));
+                        }
+
+                        let mut trailers = HeaderMap::new();
+                        trailers.insert(
+                            GRPC_STATUS,
+                            HeaderValue::from(Code::DeadlineExceeded as u16),
+                        );
+                        trailers
+                            .insert(GRPC_MESSAGE, HeaderValue::from_static("Deadline exceeded"));
+                        **body = GatewayBody::Empty;
+                        Poll::Ready(Some(Ok(Frame::trailers(trailers))))
+                    }
+                    Poll::Pending => Poll::Pending,
+                },
+            },
+            GatewayBody::Trailers(trailers) => Poll::Ready(
+                trailers
+                    .take()
+                    .map(|trailers| Ok(Frame::trailers(trailers))),
+            ),
             GatewayBody::Empty => Poll::Ready(None),
         }
     }
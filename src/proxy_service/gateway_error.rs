@@ -0,0 +1,140 @@
+use std::fmt;
+
+use http_body_util::Full;
+use hyper::{
+    body::Bytes,
+    header::{HeaderValue, CONTENT_TYPE, TRAILER},
+    HeaderMap, Response, StatusCode,
+};
+
+use crate::types::ErrorFormat;
+
+use super::{
+    gateway_body::GatewayBody,
+    grpc::{Code, GRPC_MESSAGE, GRPC_STATUS},
+};
+
+/// Errors produced by the gateway itself, as opposed to error responses of a backend.
+#[derive(Debug)]
+pub enum GatewayError {
+    NoRoute,
+    NoHealthyUpstream,
+    UpstreamConnect,
+    UpstreamRequest,
+    Timeout,
+    BadUri(String),
+}
+
+impl GatewayError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            GatewayError::NoRoute => StatusCode::NOT_FOUND,
+            GatewayError::NoHealthyUpstream => StatusCode::SERVICE_UNAVAILABLE,
+            GatewayError::UpstreamConnect => StatusCode::BAD_GATEWAY,
+            GatewayError::UpstreamRequest => StatusCode::BAD_GATEWAY,
+            GatewayError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            GatewayError::BadUri(_) => StatusCode::BAD_GATEWAY,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            GatewayError::NoRoute => "no_route",
+            GatewayError::NoHealthyUpstream => "no_healthy_upstream",
+            GatewayError::UpstreamConnect => "upstream_connect_failure",
+            GatewayError::UpstreamRequest => "upstream_request_failure",
+            GatewayError::Timeout => "upstream_timeout",
+            GatewayError::BadUri(_) => "bad_uri",
+        }
+    }
+
+    /// Message sent to the client, internal details are only logged.
+    pub fn message(&self) -> &'static str {
+        match self {
+            GatewayError::NoRoute => "No route matches the request",
+            GatewayError::NoHealthyUpstream => "No healthy upstream server is available",
+            GatewayError::UpstreamConnect => "Failed to connect to the upstream server",
+            GatewayError::UpstreamRequest => "The upstream request failed",
+            GatewayError::Timeout => "The upstream server did not respond in time",
+            GatewayError::BadUri(_) => "Failed to build the upstream request",
+        }
+    }
+
+    pub fn grpc_code(&self) -> Code {
+        match self {
+            GatewayError::NoRoute => Code::Unimplemented,
+            GatewayError::NoHealthyUpstream => Code::Unavailable,
+            GatewayError::UpstreamConnect => Code::Unavailable,
+            GatewayError::UpstreamRequest => Code::Unavailable,
+            GatewayError::Timeout => Code::DeadlineExceeded,
+            GatewayError::BadUri(_) => Code::Internal,
+        }
+    }
+
+    /// Response to a gRPC request: `200 OK` with the status in `grpc-status` and `grpc-message`
+    /// trailers, as gRPC clients ignore the HTTP status.
+    pub fn into_grpc_response(self) -> Response<GatewayBody> {
+        let mut trailers = HeaderMap::new();
+        trailers.insert(GRPC_STATUS, HeaderValue::from(self.grpc_code() as u16));
+        trailers.insert(GRPC_MESSAGE, HeaderValue::from_static(self.message()));
+
+        let mut res = Response::new(GatewayBody::Trailers(Some(trailers)));
+        res.headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+        // Lets HTTP/1.1 clients sending `TE: trailers` receive them.
+        res.headers_mut().insert(
+            TRAILER,
+            HeaderValue::from_static("grpc-status, grpc-message"),
+        );
+        res
+    }
+
+    pub fn into_response(self, format: ErrorFormat) -> Response<GatewayBody> {
+        let status = self.status();
+
+        let body = match format {
+            ErrorFormat::Empty => None,
+            ErrorFormat::Json => Some((
+                "application/json",
+                format!(
+                    r#"{{"status":{},"error":"{}","message":"{}"}}"#,
+                    status.as_u16(),
+                    self.code(),
+                    self.message()
+                ),
+            )),
+            ErrorFormat::Html => Some((
+                "text/html; charset=utf-8",
+                format!(
+                    "<!DOCTYPE html><html><head><title>{status}</title></head>\
+                     <body><h1>{status}</h1><p>{}</p></body></html>",
+                    self.message(),
+                ),
+            )),
+        };
+
+        let mut res = match body {
+            Some((content_type, body)) => {
+                let mut res = Response::new(GatewayBody::Buffered(Full::new(Bytes::from(body))));
+                res.headers_mut()
+                    .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+                res
+            }
+            None => Response::new(GatewayBody::Empty),
+        };
+        *res.status_mut() = status;
+
+        res
+    }
+}
+
+impl fmt::Display for GatewayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GatewayError::BadUri(e) => write!(f, "{}: {}", self.message(), e),
+            _ => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+impl std::error::Error for GatewayError {}
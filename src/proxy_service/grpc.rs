@@ -0,0 +1,235 @@
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicU8, Ordering},
+    time::{Duration, Instant},
+};
+
+use hyper::{
+    header::{HeaderName, HeaderValue, CONTENT_TYPE},
+    HeaderMap, StatusCode,
+};
+
+use crate::metrics::metrics;
+
+use super::gateway_body::ByteCounter;
+
+pub const GRPC_STATUS: HeaderName = HeaderName::from_static("grpc-status");
+pub const GRPC_MESSAGE: HeaderName = HeaderName::from_static("grpc-message");
+pub const GRPC_TIMEOUT: HeaderName = HeaderName::from_static("grpc-timeout");
+
+/// Longest `grpc-timeout` value, in digits.
+const MAX_TIMEOUT_DIGITS: usize = 8;
+
+/// gRPC status codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Code {
+    Ok = 0,
+    Cancelled = 1,
+    Unknown = 2,
+    InvalidArgument = 3,
+    DeadlineExceeded = 4,
+    NotFound = 5,
+    AlreadyExists = 6,
+    PermissionDenied = 7,
+    ResourceExhausted = 8,
+    FailedPrecondition = 9,
+    Aborted = 10,
+    OutOfRange = 11,
+    Unimplemented = 12,
+    Internal = 13,
+    Unavailable = 14,
+    DataLoss = 15,
+    Unauthenticated = 16,
+}
+
+const CODES: [Code; 17] = [
+    Code::Ok,
+    Code::Cancelled,
+    Code::Unknown,
+    Code::InvalidArgument,
+    Code::DeadlineExceeded,
+    Code::NotFound,
+    Code::AlreadyExists,
+    Code::PermissionDenied,
+    Code::ResourceExhausted,
+    Code::FailedPrecondition,
+    Code::Aborted,
+    Code::OutOfRange,
+    Code::Unimplemented,
+    Code::Internal,
+    Code::Unavailable,
+    Code::DataLoss,
+    Code::Unauthenticated,
+];
+
+impl Code {
+    /// Name of the code, as used in the `code` label of the gRPC metrics.
+    pub fn name(self) -> &'static str {
+        match self {
+            Code::Ok => "OK",
+            Code::Cancelled => "CANCELLED",
+            Code::Unknown => "UNKNOWN",
+            Code::InvalidArgument => "INVALID_ARGUMENT",
+            Code::DeadlineExceeded => "DEADLINE_EXCEEDED",
+            Code::NotFound => "NOT_FOUND",
+            Code::AlreadyExists => "ALREADY_EXISTS",
+            Code::PermissionDenied => "PERMISSION_DENIED",
+            Code::ResourceExhausted => "RESOURCE_EXHAUSTED",
+            Code::FailedPrecondition => "FAILED_PRECONDITION",
+            Code::Aborted => "ABORTED",
+            Code::OutOfRange => "OUT_OF_RANGE",
+            Code::Unimplemented => "UNIMPLEMENTED",
+            Code::Internal => "INTERNAL",
+            Code::Unavailable => "UNAVAILABLE",
+            Code::DataLoss => "DATA_LOSS",
+            Code::Unauthenticated => "UNAUTHENTICATED",
+        }
+    }
+
+    /// Status of a response that is not a gRPC response, e.g. from a proxy in front of the
+    /// server, as clients map it.
+    pub fn from_http(status: StatusCode) -> Self {
+        match status {
+            StatusCode::BAD_REQUEST => Code::Internal,
+            StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+            StatusCode::FORBIDDEN => Code::PermissionDenied,
+            StatusCode::NOT_FOUND => Code::Unimplemented,
+            StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT => Code::Unavailable,
+            _ => Code::Unknown,
+        }
+    }
+
+    /// Reads the `grpc-status` header of a response or its trailers.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        headers.get(GRPC_STATUS)?.to_str().ok()?.parse().ok()
+    }
+}
+
+impl FromStr for Code {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code = s.parse::<usize>().map_err(|_| ())?;
+        CODES.get(code).copied().ok_or(())
+    }
+}
+
+/// Whether the message is a gRPC message, by its `Content-Type`.
+pub fn is_grpc(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("application/grpc"))
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('+') || rest.starts_with(';'))
+}
+
+/// Service and method of a gRPC path, `/package.Service/Method`.
+pub fn method(path: &str) -> Option<(&str, &str)> {
+    let (service, method) = path.strip_prefix('/')?.split_once('/')?;
+    if service.is_empty() || method.is_empty() || method.contains('/') {
+        None
+    } else {
+        Some((service, method))
+    }
+}
+
+/// Router path of a `grpc_services` entry: every method of `package.Service`, or only the one
+/// of `package.Service/Method`.
+pub fn route_path(service: &str) -> Result<String, String> {
+    let valid = |name: &str| {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+    };
+
+    match service.split_once('/') {
+        None if valid(service) => Ok(format!("/{}/*", service)),
+        Some((service, method)) if valid(service) && valid(method) && !method.contains('.') => {
+            Ok(format!("/{}/{}", service, method))
+        }
+        _ => Err(format!(
+            "{:?} is not a package.Service or package.Service/Method name",
+            service
+        )),
+    }
+}
+
+/// Deadline of a gRPC request, from its `grpc-timeout` header, e.g. `100m` for 100ms.
+pub fn timeout(headers: &HeaderMap) -> Option<Duration> {
+    if !is_grpc(headers) {
+        return None;
+    }
+
+    let value = headers.get(GRPC_TIMEOUT)?.to_str().ok()?;
+    let unit = value.chars().last()?;
+    let amount = &value[..value.len() - unit.len_utf8()];
+    if amount.is_empty() || amount.len() > MAX_TIMEOUT_DIGITS {
+        return None;
+    }
+    let amount = amount.parse::<u64>().ok()?;
+
+    match unit {
+        'H' => Some(Duration::from_secs(amount * 3600)),
+        'M' => Some(Duration::from_secs(amount * 60)),
+        'S' => Some(Duration::from_secs(amount)),
+        'm' => Some(Duration::from_millis(amount)),
+        'u' => Some(Duration::from_micros(amount)),
+        'n' => Some(Duration::from_nanos(amount)),
+        _ => None,
+    }
+}
+
+/// `grpc-timeout` value of `timeout`, in milliseconds or, when that needs too many digits, in
+/// seconds.
+pub fn timeout_value(timeout: Duration) -> HeaderValue {
+    let max = 10u64.pow(MAX_TIMEOUT_DIGITS as u32) - 1;
+    let millis = timeout.as_millis();
+    let value = if millis <= max as u128 {
+        format!("{}m", millis)
+    } else {
+        format!("{}S", timeout.as_secs().min(max))
+    };
+    HeaderValue::from_str(&value).expect("digits and a unit are a valid header value")
+}
+
+/// Records a gRPC call in the per-method metrics when its response body is dropped, with the
+/// status of the response headers or, for most calls, of the trailers.
+pub struct GrpcCall {
+    service: String,
+    method: String,
+    start: Instant,
+    code: AtomicU8,
+}
+
+impl GrpcCall {
+    pub fn new(service: &str, method: &str, start: Instant, code: Code) -> Self {
+        Self {
+            service: service.to_string(),
+            method: method.to_string(),
+            start,
+            code: AtomicU8::new(code as u8),
+        }
+    }
+}
+
+impl ByteCounter for GrpcCall {
+    fn add(&self, _bytes: usize) {}
+
+    fn trailers(&self, trailers: &HeaderMap) {
+        if let Some(code) = Code::from_headers(trailers) {
+            self.code.store(code as u8, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Drop for GrpcCall {
+    fn drop(&mut self) {
+        let code = CODES[self.code.load(Ordering::Relaxed) as usize];
+        metrics().observe_grpc(&self.service, &self.method, code, self.start.elapsed());
+    }
+}
@@ -0,0 +1,152 @@
+use std::{fmt, net::IpAddr};
+
+use hyper::{
+    header::{HeaderName, HeaderValue},
+    HeaderMap,
+};
+
+use crate::types::HeaderRules;
+
+use super::template::{self, Part};
+
+/// Values available to header templates.
+pub struct TemplateVars<'a> {
+    pub client_ip: IpAddr,
+    pub request_id: Option<&'a str>,
+    pub route: Option<&'a str>,
+}
+
+#[derive(Debug)]
+pub struct HeaderRuleError(String);
+
+impl fmt::Display for HeaderRuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for HeaderRuleError {}
+
+enum Var {
+    ClientIp,
+    RequestId,
+    Route,
+}
+
+/// Header value with `${client_ip}`, `${request_id}` and `${route}` placeholders.
+struct Template {
+    parts: Vec<Part<Var>>,
+}
+
+impl Template {
+    fn parse(value: &str) -> Result<Self, HeaderRuleError> {
+        let parts = template::parse(value, |name| match name {
+            "client_ip" => Some(Var::ClientIp),
+            "request_id" => Some(Var::RequestId),
+            "route" => Some(Var::Route),
+            _ => None,
+        })
+        .map_err(|e| HeaderRuleError(e.to_string()))?;
+
+        Ok(Self { parts })
+    }
+
+    fn render(&self, vars: &TemplateVars) -> Option<HeaderValue> {
+        let mut value = String::new();
+
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => value.push_str(literal),
+                Part::Placeholder(Var::ClientIp) => value.push_str(&vars.client_ip.to_string()),
+                Part::Placeholder(Var::RequestId) => {
+                    value.push_str(vars.request_id.unwrap_or_default())
+                }
+                Part::Placeholder(Var::Route) => value.push_str(vars.route.unwrap_or_default()),
+            }
+        }
+
+        HeaderValue::from_str(&value).ok()
+    }
+}
+
+struct Rules {
+    remove: Vec<HeaderName>,
+    set: Vec<(HeaderName, Template)>,
+    add: Vec<(HeaderName, Template)>,
+}
+
+/// Applies the `request_headers`/`response_headers` rules of a backend and a frontend, in that
+/// order, so the frontend has the last word.
+#[derive(Default)]
+pub struct HeaderRewriter {
+    rules: Vec<Rules>,
+}
+
+impl HeaderRewriter {
+    pub fn new<'a>(
+        rules: impl IntoIterator<Item = &'a HeaderRules>,
+    ) -> Result<Self, HeaderRuleError> {
+        let rules = rules
+            .into_iter()
+            .filter(|rules| !rules.is_empty())
+            .map(|rules| {
+                Ok(Rules {
+                    remove: rules
+                        .remove
+                        .iter()
+                        .map(|name| header_name(name))
+                        .collect::<Result<_, _>>()?,
+                    set: rules
+                        .set
+                        .iter()
+                        .map(|rule| Ok((header_name(&rule.name)?, Template::parse(&rule.value)?)))
+                        .collect::<Result<_, _>>()?,
+                    add: rules
+                        .add
+                        .iter()
+                        .map(|rule| Ok((header_name(&rule.name)?, Template::parse(&rule.value)?)))
+                        .collect::<Result<_, _>>()?,
+                })
+            })
+            .collect::<Result<_, HeaderRuleError>>()?;
+
+        Ok(Self { rules })
+    }
+
+    pub fn is_noop(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Removes, then sets, then adds headers. A value that renders to an invalid header value is
+    /// skipped.
+    pub fn apply(&self, headers: &mut HeaderMap, vars: &TemplateVars) {
+        for rules in &self.rules {
+            for name in &rules.remove {
+                headers.remove(name);
+            }
+
+            for (name, template) in &rules.set {
+                match template.render(vars) {
+                    Some(value) => {
+                        headers.insert(name.clone(), value);
+                    }
+                    None => log::warn!("Skipping invalid value for header {}", name),
+                }
+            }
+
+            for (name, template) in &rules.add {
+                match template.render(vars) {
+                    Some(value) => {
+                        headers.append(name.clone(), value);
+                    }
+                    None => log::warn!("Skipping invalid value for header {}", name),
+                }
+            }
+        }
+    }
+}
+
+fn header_name(name: &str) -> Result<HeaderName, HeaderRuleError> {
+    HeaderName::from_bytes(name.as_bytes())
+        .map_err(|_| HeaderRuleError(format!("invalid header name {:?}", name)))
+}
@@ -0,0 +1,92 @@
+use hyper::{
+    header::{
+        HeaderName, HeaderValue, CONNECTION, PROXY_AUTHENTICATE, PROXY_AUTHORIZATION, TE,
+        TRANSFER_ENCODING, UPGRADE, VIA,
+    },
+    HeaderMap, Version,
+};
+
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+const KEEP_ALIVE: HeaderName = HeaderName::from_static("keep-alive");
+const PROXY_CONNECTION: HeaderName = HeaderName::from_static("proxy-connection");
+
+/// Pseudonym of the gateway in the `Via` header.
+const VIA_PSEUDONYM: &str = "oxidegate";
+
+/// Removes the headers that only apply to a single connection (RFC 9110, section 7.6.1),
+/// including the ones listed in `Connection`. `Trailer` is end-to-end and kept, as it announces
+/// the trailer fields of the body.
+pub fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let listed = headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect::<Vec<_>>();
+
+    for name in listed {
+        headers.remove(name);
+    }
+
+    for name in [
+        CONNECTION,
+        KEEP_ALIVE,
+        PROXY_CONNECTION,
+        PROXY_AUTHENTICATE,
+        PROXY_AUTHORIZATION,
+        TE,
+        TRANSFER_ENCODING,
+        UPGRADE,
+    ] {
+        headers.remove(name);
+    }
+}
+
+/// Whether the `TE` header accepts trailer fields in the response.
+pub fn accepts_trailers(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(TE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|coding| {
+            coding
+                .split(';')
+                .next()
+                .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("trailers"))
+        })
+}
+
+/// Appends the gateway to the `Via` header of a message received with `version`.
+pub fn append_via(headers: &mut HeaderMap, version: Version) {
+    let protocol = match version {
+        Version::HTTP_09 => "0.9",
+        Version::HTTP_10 => "1.0",
+        Version::HTTP_2 => "2",
+        Version::HTTP_3 => "3",
+        _ => "1.1",
+    };
+
+    append(headers, VIA, &format!("{} {}", protocol, VIA_PSEUDONYM));
+}
+
+/// Appends `value` to a comma separated header, merging repeated header lines into one.
+pub fn append(headers: &mut HeaderMap, name: HeaderName, value: &str) {
+    let existing = headers
+        .get_all(&name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<_>>();
+
+    let value = if existing.is_empty() {
+        value.to_string()
+    } else {
+        format!("{}, {}", existing.join(", "), value)
+    };
+
+    if let Ok(value) = HeaderValue::from_str(&value) {
+        headers.insert(name, value);
+    }
+}
@@ -0,0 +1,129 @@
+use std::{sync::Arc, time::Duration};
+
+use hyper::Request;
+use tokio::{task::JoinSet, time};
+
+use crate::{
+    load_balancer::health::{HealthRegistry, ServerHealth},
+    types::{HealthCheck, Timeouts, UpstreamProtocol},
+};
+
+use super::{
+    backend_url::BackendUrl,
+    gateway_body::GatewayBody,
+    proxy_handler::{build_client, HttpClient},
+};
+
+pub struct HealthChecker {
+    backend: String,
+    config: HealthCheck,
+    registry: Arc<HealthRegistry>,
+    client: HttpClient,
+}
+
+impl HealthChecker {
+    pub fn new(
+        backend: String,
+        config: HealthCheck,
+        timeouts: &Timeouts,
+        protocol: UpstreamProtocol,
+        registry: Arc<HealthRegistry>,
+    ) -> std::io::Result<Self> {
+        Ok(Self {
+            backend,
+            config,
+            registry,
+            client: build_client(timeouts, protocol)?,
+        })
+    }
+
+    pub fn spawn(self) -> tokio::task::JoinHandle<()> {
+        log::info!(
+            "Starting health checks for backend: {}, path: {}, interval: {}ms",
+            self.backend,
+            self.config.path,
+            self.config.interval_ms
+        );
+
+        let checker = Arc::new(self);
+        tokio::spawn(async move {
+            let mut interval = time::interval(Duration::from_millis(checker.config.interval_ms));
+            interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+
+            loop {
+                interval.tick().await;
+                checker.probe_all().await;
+            }
+        })
+    }
+
+    async fn probe_all(self: &Arc<Self>) {
+        let mut probes = JoinSet::new();
+
+        for (server, health) in self.registry.servers() {
+            let checker = self.clone();
+            probes.spawn(async move {
+                let healthy = checker.probe(&server).await;
+                checker.record(&server, &health, healthy);
+            });
+        }
+
+        while probes.join_next().await.is_some() {}
+    }
+
+    async fn probe(&self, server: &str) -> bool {
+        let uri = BackendUrl::parse(server)
+            .map_err(|e| e.to_string())
+            .and_then(|backend| {
+                let (path, query) = match self.config.path.split_once('?') {
+                    Some((path, query)) => (path, Some(query)),
+                    None => (self.config.path.as_str(), None),
+                };
+                backend.join(path, query).map_err(|e| e.to_string())
+            });
+
+        let uri = match uri {
+            Ok(uri) => uri,
+            Err(e) => {
+                log::warn!("Invalid health check uri for server {}: {}", server, e);
+                return false;
+            }
+        };
+
+        let req = match Request::get(uri).body(GatewayBody::Empty) {
+            Ok(req) => req,
+            Err(e) => {
+                log::warn!("Failed to build health check request: {}", e);
+                return false;
+            }
+        };
+
+        let timeout_duration = Duration::from_millis(self.config.timeout_ms);
+
+        match time::timeout(timeout_duration, self.client.request(req)).await {
+            Ok(Ok(res)) => {
+                let status = res.status().as_u16();
+                log::debug!("Health check for {} returned status {}", server, status);
+                self.config.expected_status.contains(status)
+            }
+            Ok(Err(e)) => {
+                log::debug!("Health check for {} failed: {}", server, e);
+                false
+            }
+            Err(_) => {
+                log::debug!("Health check for {} timed out", server);
+                false
+            }
+        }
+    }
+
+    fn record(&self, server: &str, health: &ServerHealth, healthy: bool) {
+        if healthy {
+            if health.record_success(self.config.healthy_threshold) {
+                log::info!("Server {} in backend {} is healthy", server, self.backend);
+            }
+        } else if health.record_failure(self.config.unhealthy_threshold) {
+            log::warn!("Server {} in backend {} is unhealthy", server, self.backend);
+        }
+    }
+}
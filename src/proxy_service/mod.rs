@@ -1,3 +1,18 @@
+pub mod access_log;
+pub mod backend_url;
+pub mod context;
+pub mod forwarded;
 pub mod gateway_body;
+pub mod gateway_error;
+pub mod grpc;
+pub mod header_rewriter;
+pub mod headers;
+pub mod health_checker;
+pub mod path_rewriter;
+pub mod predicates;
 pub mod proxy_bridge;
 pub mod proxy_handler;
+pub mod request_id;
+pub mod router;
+pub mod template;
+pub mod upgrade;
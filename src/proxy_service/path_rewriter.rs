@@ -0,0 +1,63 @@
+use regex::Regex;
+
+use crate::types::Frontend;
+
+/// Rewrites the request path of a frontend before it is sent upstream. The prefix is stripped
+/// first, then the rewrite rules are applied in order, then the prefix is added.
+pub struct PathRewriter {
+    strip_prefix: Option<String>,
+    add_prefix: Option<String>,
+    rules: Vec<(Regex, String)>,
+}
+
+impl PathRewriter {
+    pub fn new(frontend: &Frontend) -> Result<Self, regex::Error> {
+        let rules = frontend
+            .rewrite
+            .iter()
+            .map(|rule| Ok((Regex::new(&rule.pattern)?, rule.replacement.clone())))
+            .collect::<Result<Vec<_>, regex::Error>>()?;
+
+        Ok(Self {
+            strip_prefix: frontend
+                .strip_prefix
+                .as_ref()
+                .map(|prefix| prefix.trim_end_matches('/').to_string()),
+            add_prefix: frontend
+                .add_prefix
+                .as_ref()
+                .map(|prefix| prefix.trim_end_matches('/').to_string()),
+            rules,
+        })
+    }
+
+    pub fn is_noop(&self) -> bool {
+        self.strip_prefix.is_none() && self.add_prefix.is_none() && self.rules.is_empty()
+    }
+
+    pub fn rewrite(&self, path: &str) -> String {
+        let mut path = path.to_string();
+
+        if let Some(prefix) = &self.strip_prefix {
+            if let Some(rest) = path.strip_prefix(prefix.as_str()) {
+                if rest.is_empty() || rest.starts_with('/') {
+                    path = rest.to_string();
+                }
+            }
+        }
+
+        for (pattern, replacement) in &self.rules {
+            path = pattern.replace(&path, replacement.as_str()).into_owned();
+        }
+
+        if let Some(prefix) = &self.add_prefix {
+            path = format!("{}{}", prefix, path);
+        }
+
+        if !path.starts_with('/') {
+            path.insert(0, '/');
+        }
+
+        path
+    }
+}
@@ -0,0 +1,91 @@
+use hyper::{header::COOKIE, Request};
+
+use crate::types::{RoutePredicates, ValueMatch};
+
+/// Evaluates every predicate of a frontend against the request, all of them must match.
+pub fn matches<B>(predicates: &RoutePredicates, req: &Request<B>) -> bool {
+    matches_method(&predicates.methods, req)
+        && predicates
+            .headers
+            .iter()
+            .all(|predicate| matches_header(predicate, req))
+        && predicates
+            .query
+            .iter()
+            .all(|predicate| matches_query(predicate, req))
+        && predicates
+            .cookies
+            .iter()
+            .all(|predicate| matches_cookie(predicate, req))
+}
+
+fn matches_method<B>(methods: &[String], req: &Request<B>) -> bool {
+    methods.is_empty()
+        || methods
+            .iter()
+            .any(|method| method.eq_ignore_ascii_case(req.method().as_str()))
+}
+
+fn matches_value(predicate: &ValueMatch, value: &str) -> bool {
+    predicate
+        .value
+        .as_ref()
+        .is_none_or(|expected| expected == value)
+}
+
+fn matches_header<B>(predicate: &ValueMatch, req: &Request<B>) -> bool {
+    req.headers()
+        .get_all(predicate.name.as_str())
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| matches_value(predicate, value))
+}
+
+fn matches_query<B>(predicate: &ValueMatch, req: &Request<B>) -> bool {
+    let Some(query) = req.uri().query() else {
+        return false;
+    };
+
+    query.split('&').any(|pair| {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        percent_decode(name) == predicate.name && matches_value(predicate, &percent_decode(value))
+    })
+}
+
+fn matches_cookie<B>(predicate: &ValueMatch, req: &Request<B>) -> bool {
+    req.headers()
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .any(|cookie| {
+            let (name, value) = cookie.trim().split_once('=').unwrap_or((cookie.trim(), ""));
+            name == predicate.name && matches_value(predicate, value.trim_matches('"'))
+        })
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => match value
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(byte) => {
+                    decoded.push(byte);
+                    i += 2;
+                }
+                None => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -1,44 +1,272 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc, time::Instant};
 
-use crate::types::Frontend;
-use hyper::{body::Incoming, Request, Response, StatusCode};
+use crate::{
+    metrics::{metrics, RequestLabels},
+    telemetry::{Span, SpanContext, SpanKind, Tracer},
+    types::{ErrorFormat, Frontend, RequestIdFormat, ServerSettings},
+};
+use hyper::{
+    body::Incoming,
+    header::{HeaderValue, HOST, TE},
+    Request, Response, StatusCode,
+};
 
-use super::{gateway_body::GatewayBody, proxy_handler::ProxyHandler};
+use super::{
+    access_log::{AccessEntry, AccessLogger},
+    context::{RequestContext, RequestStats},
+    forwarded::{CidrError, Forwarder},
+    gateway_body::GatewayBody,
+    gateway_error::GatewayError,
+    grpc,
+    headers::{accepts_trailers, strip_hop_by_hop, X_REQUEST_ID},
+    predicates,
+    proxy_handler::ProxyHandler,
+    request_id,
+    router::Router,
+    upgrade,
+};
 
 pub struct ProxyBridge {
-    proxy_handlers: Arc<Vec<(Frontend, Arc<ProxyHandler>)>>,
+    router: Router<(Frontend, Arc<ProxyHandler>)>,
+    error_format: ErrorFormat,
+    forwarder: Forwarder,
+    request_id_format: RequestIdFormat,
+    access_log: Option<Arc<AccessLogger>>,
+    tracer: Option<Arc<Tracer>>,
 }
 
 impl ProxyBridge {
-    pub fn new(proxy_handlers: Arc<Vec<(Frontend, Arc<ProxyHandler>)>>) -> Self {
-        Self { proxy_handlers }
+    pub fn new(
+        proxy_handlers: Arc<Vec<(Frontend, Arc<ProxyHandler>)>>,
+        settings: &ServerSettings,
+        access_log: Option<Arc<AccessLogger>>,
+        tracer: Option<Arc<Tracer>>,
+    ) -> Result<Self, CidrError> {
+        let mut router = Router::new();
+
+        for (frontend, handler) in proxy_handlers.iter() {
+            // gRPC services are matched as paths, the config validates them.
+            let paths = frontend
+                .path_prefix
+                .iter()
+                .cloned()
+                .chain(
+                    frontend
+                        .grpc_services
+                        .iter()
+                        .filter_map(|service| grpc::route_path(service).ok()),
+                )
+                .collect::<Vec<_>>();
+
+            router.insert(
+                &frontend.hosts,
+                &paths,
+                frontend.priority,
+                frontend.predicates.len(),
+                (frontend.clone(), handler.clone()),
+            );
+        }
+
+        Ok(Self {
+            router,
+            error_format: settings.error_format,
+            forwarder: Forwarder::new(settings)?,
+            request_id_format: settings.request_id,
+            access_log,
+            tracer,
+        })
     }
 
-    pub async fn determine(&self, req: Request<Incoming>) -> Response<GatewayBody> {
-        log::info!("Request recieced with path: {:?}", req.uri().path());
-
-        let path = req.uri().path();
-        let handler = self.proxy_handlers.iter().find(|(frontend, _)| {
-            frontend.path_prefix.iter().any(|prefix| {
-                if prefix == "/*" {
-                    true
-                } else if prefix.ends_with("/*") {
-                    let trimmed_prefix = &prefix[..prefix.len() - 1];
-                    path.starts_with(trimmed_prefix)
-                } else {
-                    path == prefix
-                }
-            })
+    pub async fn determine(
+        &self,
+        mut req: Request<Incoming>,
+        peer: SocketAddr,
+    ) -> Response<GatewayBody> {
+        let _in_flight = metrics().request_started();
+        let start = Instant::now();
+        strip_client_hop_by_hop(&mut req);
+
+        let method = req.method().clone();
+        let grpc_method = grpc::is_grpc(req.headers())
+            .then(|| grpc::method(req.uri().path()))
+            .flatten()
+            .map(|(service, method)| (service.to_string(), method.to_string()));
+
+        let request_id = self.request_id(&req, peer);
+
+        match HeaderValue::from_str(&request_id) {
+            Ok(value) => {
+                req.headers_mut().insert(X_REQUEST_ID, value);
+            }
+            Err(_) => log::warn!("Invalid request id {:?}", request_id),
+        }
+
+        let client_ip = self.forwarder.client_ip(req.headers(), peer);
+
+        let mut span = self.tracer.as_ref().map(|tracer| {
+            let parent = SpanContext::extract(req.headers());
+            let mut span = tracer.start_span(method.as_str(), SpanKind::Server, parent.as_ref());
+            span.set_attribute("http.request.method", method.as_str());
+            span.set_attribute("url.path", req.uri().path());
+            span.set_attribute("client.address", client_ip.to_string());
+            span.set_attribute("request.id", request_id.clone());
+            if let Some(host) = request_host(&req) {
+                span.set_attribute("server.address", host);
+            }
+            span
         });
 
+        let ctx = RequestContext {
+            client_ip,
+            request_id: request_id.clone(),
+            stats: Arc::new(RequestStats::default()),
+            trace: span.as_ref().and_then(Span::parent),
+        };
+
+        let access_entry = self.access_log.as_ref().map(|access_log| {
+            AccessEntry::start(
+                access_log.clone(),
+                &req,
+                ctx.client_ip,
+                request_id.clone(),
+                ctx.stats.clone(),
+            )
+        });
+
+        let mut res = request_id::scope(request_id, self.dispatch(req, peer, &ctx))
+            .await
+            .map(|body| body.scoped(&ctx.request_id));
+
+        if let Ok(value) = HeaderValue::from_str(&ctx.request_id) {
+            res.headers_mut().insert(X_REQUEST_ID, value);
+        }
+
+        if let Some(span) = &mut span {
+            if let Some(frontend) = ctx.stats.frontend() {
+                span.set_attribute("http.route", frontend);
+            }
+            span.set_attribute("http.response.status_code", res.status().as_u16());
+            if res.status().is_server_error() {
+                span.set_error(res.status().to_string());
+            }
+        }
+
+        let upstream = ctx.stats.upstream();
+        metrics().observe_request(
+            RequestLabels::new(
+                ctx.stats.frontend().as_deref(),
+                ctx.stats.backend().as_deref(),
+                upstream.as_ref().map(|(server, _)| server.as_str()),
+                &method,
+                res.status(),
+            ),
+            start.elapsed(),
+        );
+
+        // Calls without a route are not counted per method, the path could be anything.
+        if let (Some((service, method)), Some(_)) = (&grpc_method, ctx.stats.backend()) {
+            let code = match grpc::Code::from_headers(res.headers()) {
+                Some(code) => code,
+                None if res.status() == StatusCode::OK => grpc::Code::Unknown,
+                None => grpc::Code::from_http(res.status()),
+            };
+            let call = grpc::GrpcCall::new(service, method, start, code);
+            res = res.map(|body| body.counted(Arc::new(call)));
+        }
+
+        match access_entry {
+            Some(mut access_entry) => {
+                access_entry.set_status(res.status());
+                res.map(|body| body.counted(Arc::new(access_entry)))
+            }
+            None => res,
+        }
+    }
+
+    async fn dispatch(
+        &self,
+        mut req: Request<Incoming>,
+        peer: SocketAddr,
+        ctx: &RequestContext,
+    ) -> Response<GatewayBody> {
+        log::debug!("Request recieced with path: {:?}", req.uri().path());
+
+        let is_grpc = grpc::is_grpc(req.headers());
+
+        let handler = self
+            .router
+            .route(request_host(&req), req.uri().path(), |(frontend, _)| {
+                predicates::matches(&frontend.predicates, &req)
+            });
+
         log::debug!("Handler found: {:?}", handler.is_some());
 
-        match handler {
-            Some((_, handler)) => handler.handle(req).await,
-            None => Response::builder()
-                .status(StatusCode::SERVICE_UNAVAILABLE)
-                .body(GatewayBody::Empty)
-                .unwrap(),
+        let (res, handler) = match handler {
+            Some((frontend, handler)) => {
+                ctx.stats
+                    .set_route(frontend.name.clone(), &frontend.backend);
+
+                let host = request_host(&req).map(str::to_string);
+                self.forwarder
+                    .apply(req.headers_mut(), host.as_deref(), peer);
+
+                (handler.handle(req, ctx).await, Some(handler))
+            }
+            None => (Err(GatewayError::NoRoute), None),
+        };
+
+        res.unwrap_or_else(|e| {
+            log::debug!("Responding with gateway error: {}", e);
+            let mut res = if is_grpc {
+                e.into_grpc_response()
+            } else {
+                e.into_response(self.error_format)
+            };
+            if let Some(handler) = handler {
+                handler.rewrite_response(&mut res, ctx);
+            }
+            res
+        })
+    }
+
+    /// Keeps the `X-Request-Id` of a trusted proxy, otherwise generates a new one.
+    fn request_id<B>(&self, req: &Request<B>, peer: SocketAddr) -> String {
+        let incoming = req
+            .headers()
+            .get(X_REQUEST_ID)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| request_id::is_valid(id));
+
+        match incoming {
+            Some(id) if self.forwarder.is_trusted(peer.ip()) => id.to_string(),
+            _ => request_id::generate(self.request_id_format),
         }
     }
 }
+
+/// Removes the hop-by-hop headers of a client request before the gateway adds its own, so the
+/// client cannot list them in `Connection` to have them removed. `TE: trailers` and the upgrade
+/// headers are put back for the `ProxyHandler`, which forwards them itself.
+fn strip_client_hop_by_hop<B>(req: &mut Request<B>) {
+    let trailers = accepts_trailers(req.headers());
+    let upgrade = upgrade::requested(req.version(), req.headers());
+
+    strip_hop_by_hop(req.headers_mut());
+
+    if trailers {
+        req.headers_mut()
+            .insert(TE, HeaderValue::from_static("trailers"));
+    }
+    if let Some(protocol) = upgrade {
+        upgrade::set_headers(req.headers_mut(), protocol);
+    }
+}
+
+/// Authority of the request, taken from the `:authority` for HTTP/2 and from the `Host` header
+/// otherwise.
+pub fn request_host<B>(req: &Request<B>) -> Option<&str> {
+    match req.uri().authority() {
+        Some(authority) => Some(authority.as_str()),
+        None => req.headers().get(HOST)?.to_str().ok(),
+    }
+}
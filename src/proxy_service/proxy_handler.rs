@@ -1,128 +1,723 @@
-use hyper::{body::Incoming, Request, Response, StatusCode, Uri};
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    body::{Bytes, Incoming},
+    header::{HeaderValue, CONTENT_LENGTH, HOST, TE, UPGRADE},
+    http::request::Parts,
+    HeaderMap, Method, Request, Response, StatusCode, Uri,
+};
 use hyper_rustls::{ConfigBuilderExt, HttpsConnector};
 use hyper_util::{
     client::legacy::{connect::HttpConnector, Client},
     rt::TokioExecutor,
 };
-use std::sync::Arc;
-use tokio::time::timeout;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    error::Error as StdError,
+    io,
+    sync::{atomic::Ordering, Arc, RwLock},
+    time::Duration,
+};
+use tokio::time::{sleep, timeout, Instant};
 use tokio_rustls::rustls;
 
-use crate::load_balancer::factory::LoadBalancer;
+use crate::{
+    load_balancer::{
+        factory::{LoadBalancer, SelectedLB},
+        outlier_detection::{Outcome, OutlierDetector},
+    },
+    metrics::metrics,
+    server::shutdown::Shutdown,
+    telemetry::SpanKind,
+    types::{Backend, Frontend, Retry, RetryOn, Timeouts, UpstreamProtocol},
+};
+
+use super::{
+    backend_url::{BackendUrl, BackendUrlError},
+    context::RequestContext,
+    gateway_body::{BoxError, GatewayBody},
+    gateway_error::GatewayError,
+    grpc,
+    header_rewriter::{HeaderRewriter, TemplateVars},
+    headers::{accepts_trailers, append_via, strip_hop_by_hop},
+    path_rewriter::PathRewriter,
+    request_id, upgrade,
+};
+
+pub type HttpClient = Client<HttpsConnector<HttpConnector>, GatewayBody>;
 
-use super::gateway_body::GatewayBody;
+/// Builds the client of a backend. The ALPN protocols offered over TLS follow `protocol`, and
+/// `Http2` and `H2c` speak HTTP/2 without falling back to HTTP/1.1.
+pub fn build_client(
+    timeouts: &Timeouts,
+    protocol: UpstreamProtocol,
+) -> std::io::Result<HttpClient> {
+    let c = rustls::ClientConfig::builder()
+        .with_native_roots()?
+        .with_no_client_auth();
+
+    let mut http = HttpConnector::new();
+    http.enforce_http(false);
+    http.set_connect_timeout(timeouts.connect_timeout());
+
+    let https = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_tls_config(c)
+        .https_or_http();
+    let https = match protocol {
+        UpstreamProtocol::Http1 | UpstreamProtocol::H2c => {
+            https.enable_http1().wrap_connector(http)
+        }
+        UpstreamProtocol::Http2 => https.enable_http2().wrap_connector(http),
+        UpstreamProtocol::Auto => https.enable_all_versions().wrap_connector(http),
+    };
+
+    let mut builder = Client::builder(TokioExecutor::new());
+    builder.pool_idle_timeout(timeouts.pool_idle_timeout());
+    if matches!(protocol, UpstreamProtocol::Http2 | UpstreamProtocol::H2c) {
+        builder.http2_only(true);
+    }
 
-type HttpClient = Client<HttpsConnector<HttpConnector>, GatewayBody>;
+    Ok(builder.build(https))
+}
+
+enum UpstreamError {
+    Request(hyper_util::client::legacy::Error),
+    Timeout,
+}
+
+impl UpstreamError {
+    fn is_timeout(&self) -> bool {
+        match self {
+            UpstreamError::Request(e) => {
+                let mut source = e.source();
+                while let Some(err) = source {
+                    if let Some(io_err) = err.downcast_ref::<io::Error>() {
+                        return io_err.kind() == io::ErrorKind::TimedOut;
+                    }
+                    source = err.source();
+                }
+                false
+            }
+            UpstreamError::Timeout => true,
+        }
+    }
+
+    /// Kind label of the `oxidegate_upstream_errors_total` metric.
+    fn kind(&self) -> &'static str {
+        match self {
+            e if e.is_timeout() => "timeout",
+            UpstreamError::Request(e) if e.is_connect() => "connect",
+            _ => "request",
+        }
+    }
+
+    fn is_retryable(&self, retry: &Retry) -> bool {
+        match self {
+            UpstreamError::Request(e) => {
+                e.is_connect() && retry.retry_on.contains(&RetryOn::ConnectFailure)
+            }
+            UpstreamError::Timeout => retry.retry_on.contains(&RetryOn::Timeout),
+        }
+    }
+}
+
+impl From<UpstreamError> for GatewayError {
+    fn from(e: UpstreamError) -> Self {
+        match e {
+            e if e.is_timeout() => GatewayError::Timeout,
+            UpstreamError::Request(e) if e.is_connect() => GatewayError::UpstreamConnect,
+            _ => GatewayError::UpstreamRequest,
+        }
+    }
+}
 
 pub struct ProxyHandler {
     pub client: HttpClient,
+    backend: String,
     pub load_balancer: Arc<dyn LoadBalancer>,
+    pub outlier_detector: Option<Arc<OutlierDetector>>,
+    retry: Option<Retry>,
+    timeouts: Timeouts,
+    path_rewriter: PathRewriter,
+    preserve_host: bool,
+    protocol: UpstreamProtocol,
+    /// Parsed URL of every server, including the ones added while running.
+    urls: RwLock<HashMap<String, Arc<BackendUrl>>>,
+    route: Option<String>,
+    request_headers: HeaderRewriter,
+    response_headers: HeaderRewriter,
 }
 
 impl ProxyHandler {
-    pub fn new(balancer: Arc<dyn LoadBalancer>) -> Self {
-        let c = rustls::ClientConfig::builder()
-            .with_native_roots()
-            .unwrap()
-            .with_no_client_auth();
-
-        let https = hyper_rustls::HttpsConnectorBuilder::new()
-            .with_tls_config(c)
-            .https_or_http()
-            .enable_http1()
-            .build();
-
-        let client: Client<_, GatewayBody> = Client::builder(TokioExecutor::new()).build(https);
-
-        Self {
-            client,
+    pub fn new(
+        frontend: &Frontend,
+        backend: &Backend,
+        balancer: Arc<dyn LoadBalancer>,
+        outlier_detector: Option<Arc<OutlierDetector>>,
+    ) -> Result<Self, Box<dyn StdError>> {
+        let timeouts = frontend.timeouts.or(&backend.timeouts);
+        let urls = backend
+            .servers
+            .iter()
+            .filter_map(|server| {
+                let url = BackendUrl::parse(&server.server).ok()?;
+                Some((server.server.clone(), Arc::new(url)))
+            })
+            .collect();
+
+        Ok(Self {
+            client: build_client(&timeouts, backend.protocol)?,
+            backend: backend.name.clone(),
             load_balancer: balancer,
+            outlier_detector,
+            retry: backend.retry.clone(),
+            timeouts,
+            path_rewriter: PathRewriter::new(frontend)?,
+            preserve_host: backend.preserve_host,
+            protocol: backend.protocol,
+            urls: RwLock::new(urls),
+            route: frontend.name.clone(),
+            request_headers: HeaderRewriter::new([
+                &backend.request_headers,
+                &frontend.request_headers,
+            ])?,
+            response_headers: HeaderRewriter::new([
+                &backend.response_headers,
+                &frontend.response_headers,
+            ])?,
+        })
+    }
+
+    pub async fn handle(
+        &self,
+        mut req: Request<Incoming>,
+        ctx: &RequestContext,
+    ) -> Result<Response<GatewayBody>, GatewayError> {
+        let vars = TemplateVars {
+            client_ip: ctx.client_ip,
+            request_id: Some(&ctx.request_id),
+            route: self.route.as_deref(),
+        };
+
+        if !self.request_headers.is_noop() {
+            self.request_headers.apply(req.headers_mut(), &vars);
+        }
+
+        // Bounds every attempt, the backoff between them and the streamed bodies of a gRPC call.
+        let deadline = grpc::timeout(req.headers()).map(|timeout| Instant::now() + timeout);
+
+        let res = match &self.retry {
+            // Upgrades cannot be replayed, the client is already switched.
+            _ if self.upgrade_protocol(&req).is_some() => {
+                self.handle_once(self.streamed(req, deadline), deadline, ctx)
+                    .await
+            }
+            Some(retry)
+                if retry.max_attempts > 1
+                    && (!retry.idempotent_only || is_idempotent(req.method())) =>
+            {
+                self.handle_with_retries(req, retry, deadline, ctx).await
+            }
+            _ => {
+                self.handle_once(self.streamed(req, deadline), deadline, ctx)
+                    .await
+            }
+        };
+
+        res.map(|mut res| {
+            self.rewrite_response(&mut res, ctx);
+            res
+        })
+    }
+
+    /// Applies the `response_headers` rules, also used for the gateway errors of the route.
+    pub fn rewrite_response<B>(&self, res: &mut Response<B>, ctx: &RequestContext) {
+        if self.response_headers.is_noop() {
+            return;
         }
+
+        let vars = TemplateVars {
+            client_ip: ctx.client_ip,
+            request_id: Some(&ctx.request_id),
+            route: self.route.as_deref(),
+        };
+        self.response_headers.apply(res.headers_mut(), &vars);
     }
 
-    pub async fn handle(&self, req: Request<Incoming>) -> Response<GatewayBody> {
+    async fn handle_with_retries(
+        &self,
+        req: Request<Incoming>,
+        retry: &Retry,
+        deadline: Option<Instant>,
+        ctx: &RequestContext,
+    ) -> Result<Response<GatewayBody>, GatewayError> {
+        let content_length = req
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<usize>().ok());
+
+        if content_length.is_some_and(|length| length > retry.max_body_bytes) {
+            log::debug!("Request body exceeds the retry buffer, proxying without retries");
+            return self
+                .handle_once(self.streamed(req, deadline), deadline, ctx)
+                .await;
+        }
+
+        let (parts, body) = self.streamed(req, deadline).into_parts();
+        let (body, trailers) = match buffer_body(body, retry.max_body_bytes).await {
+            Ok(BufferedBody::Complete(body, trailers)) => (body, trailers),
+            Ok(BufferedBody::Overflow(prefix, rest)) => {
+                log::debug!("Request body exceeds the retry buffer, proxying without retries");
+                let body = GatewayBody::Prefixed {
+                    prefix: Some(prefix),
+                    body: Box::new(rest),
+                };
+                return self
+                    .handle_once(Request::from_parts(parts, body), deadline, ctx)
+                    .await;
+            }
+            Err(e) => {
+                log::warn!("Failed to read request body: {}", e);
+                return Err(GatewayError::UpstreamRequest);
+            }
+        };
+        ctx.stats
+            .bytes_in
+            .store(body.len() as u64, Ordering::Relaxed);
+
+        let per_try_timeout = retry
+            .per_try_timeout_ms
+            .map(Duration::from_millis)
+            .unwrap_or(self.timeouts.request_timeout());
+
+        let mut tried: Vec<String> = Vec::new();
+        // Outcome of the last attempt, returned when no server is left to retry on.
+        let mut last = None;
+
+        for attempt in 1..=retry.max_attempts {
+            let Some(backend) = self.next_untried(&tried).await else {
+                break;
+            };
+            tried.push(backend.server.clone());
+
+            let backend_uri = self.build_backend_uri(&parts.uri, &backend.server)?;
+            log::debug!(
+                "Proxying request to: {}, attempt: {}/{}",
+                backend_uri,
+                attempt,
+                retry.max_attempts
+            );
+
+            let new_req =
+                self.build_request(&parts, &backend_uri, body.clone(), trailers.clone())?;
+
+            let last_attempt = attempt == retry.max_attempts;
+
+            match self
+                .send(new_req, &backend.server, per_try_timeout, deadline, ctx)
+                .await
+            {
+                Ok(res) if !last_attempt && retry.status_codes.contains(&res.status().as_u16()) => {
+                    log::warn!(
+                        "Retrying request, server {} returned status {}",
+                        backend.server,
+                        res.status()
+                    );
+                    last = Some(Ok(res));
+                }
+                Ok(res) => return Ok(self.gateway_response(res, deadline)),
+                Err(e) if !last_attempt && e.is_retryable(retry) => {
+                    log::warn!("Retrying request, server {} failed", backend.server);
+                    last = Some(Err(e));
+                }
+                Err(e) => return Err(e.into()),
+            }
+
+            let delay = backoff(retry, attempt);
+            if deadline.is_some_and(|deadline| Instant::now() + delay >= deadline) {
+                log::debug!("gRPC deadline reached, not retrying");
+                break;
+            }
+            sleep(delay).await;
+        }
+
+        match last {
+            Some(Ok(res)) => Ok(self.gateway_response(res, deadline)),
+            Some(Err(e)) => Err(e.into()),
+            None => Err(GatewayError::NoHealthyUpstream),
+        }
+    }
+
+    async fn handle_once(
+        &self,
+        req: Request<GatewayBody>,
+        deadline: Option<Instant>,
+        ctx: &RequestContext,
+    ) -> Result<Response<GatewayBody>, GatewayError> {
         let selected_lb = self.load_balancer.next().await;
 
         match selected_lb {
             Some(backend) => {
-                let backend_uri = self.build_backend_uri(&req, &backend.server);
+                let backend_uri = self.build_backend_uri(req.uri(), &backend.server)?;
                 log::debug!("Proxying request to: {}", backend_uri);
 
-                self.proxy_request(req, &backend_uri).await
+                self.proxy_request(req, &backend, &backend_uri, deadline, ctx)
+                    .await
             }
-            None => Response::builder()
-                .status(StatusCode::SERVICE_UNAVAILABLE)
-                .body(GatewayBody::Empty)
-                .unwrap(),
+            None => Err(GatewayError::NoHealthyUpstream),
         }
     }
 
-    fn build_backend_uri(&self, req: &Request<Incoming>, backend: &str) -> Uri {
-        let path = req
-            .uri()
-            .path_and_query()
-            .map(|p| p.as_str())
-            .unwrap_or("/");
-        format!("{}{}", backend, path).parse().unwrap()
+    /// Prefers a server that has not been tried yet, falling back to whatever the balancer picks.
+    async fn next_untried(&self, tried: &[String]) -> Option<Arc<SelectedLB>> {
+        let servers = self.load_balancer.health().len();
+        let mut selected = None;
+
+        for _ in 0..servers.max(1) {
+            let backend = self.load_balancer.next().await?;
+            if !tried.contains(&backend.server) {
+                return Some(backend);
+            }
+            selected = Some(backend);
+        }
+
+        selected
     }
 
-    async fn proxy_request(
+    fn build_backend_uri(&self, uri: &Uri, backend: &str) -> Result<Uri, GatewayError> {
+        let path = if self.path_rewriter.is_noop() {
+            Cow::Borrowed(uri.path())
+        } else {
+            Cow::Owned(self.path_rewriter.rewrite(uri.path()))
+        };
+
+        let backend_uri = self
+            .backend_url(backend)
+            .map_err(|e| e.to_string())
+            .and_then(|backend| backend.join(&path, uri.query()).map_err(|e| e.to_string()));
+
+        backend_uri.map_err(|e| {
+            log::error!("Failed to build backend uri for server {}: {}", backend, e);
+            GatewayError::BadUri(e)
+        })
+    }
+
+    /// Parsed URL of a server. Servers added since the handler was built are parsed on their
+    /// first request, forgetting the servers that were removed.
+    fn backend_url(&self, server: &str) -> Result<Arc<BackendUrl>, BackendUrlError> {
+        if let Some(url) = self.urls.read().unwrap().get(server) {
+            return Ok(url.clone());
+        }
+
+        let url = Arc::new(BackendUrl::parse(server)?);
+        let health = self.load_balancer.health();
+        let mut urls = self.urls.write().unwrap();
+        urls.retain(|server, _| health.get(server).is_some());
+        urls.insert(server.to_string(), url.clone());
+        Ok(url)
+    }
+
+    fn build_request(
         &self,
-        req: Request<Incoming>,
+        parts: &Parts,
         backend_uri: &Uri,
-    ) -> Response<GatewayBody> {
-        let timeout_duration = std::time::Duration::from_secs(5);
-
-        let headers = req.headers().clone();
-        let method = req.method().clone();
-        let uri = backend_uri;
-        let body = req.into_body();
+        body: Bytes,
+        trailers: Option<HeaderMap>,
+    ) -> Result<Request<GatewayBody>, GatewayError> {
+        let body = match trailers {
+            Some(trailers) => GatewayBody::Prefixed {
+                prefix: (!body.is_empty()).then_some(body),
+                body: Box::new(GatewayBody::Trailers(Some(trailers))),
+            },
+            None => GatewayBody::Buffered(Full::new(body)),
+        };
 
-        let new_req = Request::builder()
-            .method(method)
-            .uri(uri)
-            .body(GatewayBody::Incomming(body))
+        let mut req = Request::builder()
+            .method(parts.method.clone())
+            .uri(backend_uri)
+            .body(body)
             .map_err(|e| {
                 log::error!("Failed to build request: {}", e);
-                e
-            });
+                GatewayError::BadUri(e.to_string())
+            })?;
 
-        let new_req = match new_req {
-            Ok(mut req) => {
-                *req.headers_mut() = headers;
-                req
+        *req.headers_mut() = self.upstream_headers(parts, backend_uri);
+        Ok(req)
+    }
+
+    /// Headers of the upstream request: the client's end-to-end headers, a `Via` entry and the
+    /// `Host` selected by `preserve_host`. `TE: trailers` is kept, as gRPC servers require it.
+    fn upstream_headers(&self, parts: &Parts, backend_uri: &Uri) -> HeaderMap {
+        let mut headers = parts.headers.clone();
+        let trailers = accepts_trailers(&headers);
+        strip_hop_by_hop(&mut headers);
+        append_via(&mut headers, parts.version);
+
+        if trailers {
+            headers.insert(TE, HeaderValue::from_static("trailers"));
+        }
+
+        let host = if self.preserve_host {
+            match headers.get(HOST) {
+                Some(host) => Some(host.clone()),
+                None => parts
+                    .uri
+                    .authority()
+                    .and_then(|authority| HeaderValue::from_str(authority.as_str()).ok()),
             }
-            Err(_) => {
-                return Response::builder()
-                    .status(StatusCode::INTERNAL_SERVER_ERROR)
-                    .body(GatewayBody::Empty)
-                    .unwrap();
+        } else {
+            backend_uri
+                .authority()
+                .and_then(|authority| HeaderValue::from_str(authority.as_str()).ok())
+        };
+
+        match host {
+            Some(host) => headers.insert(HOST, host),
+            None => headers.remove(HOST),
+        };
+
+        headers
+    }
+
+    fn report(&self, server: &str, outcome: Outcome) {
+        if let Some(outlier_detector) = &self.outlier_detector {
+            outlier_detector.report(server, outcome);
+        }
+    }
+
+    async fn send(
+        &self,
+        mut req: Request<GatewayBody>,
+        server: &str,
+        timeout_duration: Duration,
+        deadline: Option<Instant>,
+        ctx: &RequestContext,
+    ) -> Result<Response<Incoming>, UpstreamError> {
+        // A gRPC deadline shorter than the request timeout wins, and the server is told how much
+        // of it is left.
+        let timeout_duration = match deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining < Duration::from_millis(1) {
+                    log::debug!("gRPC deadline reached before sending the request");
+                    metrics().timeout("grpc_deadline");
+                    return Err(UpstreamError::Timeout);
+                }
+                req.headers_mut()
+                    .insert(grpc::GRPC_TIMEOUT, grpc::timeout_value(remaining));
+                remaining.min(timeout_duration)
             }
+            None => timeout_duration,
         };
 
-        match timeout(timeout_duration, self.client.request(new_req)).await {
+        let mut span = ctx.trace.as_ref().map(|parent| {
+            let mut span = parent.child(req.method().as_str(), SpanKind::Client);
+            span.set_attribute("http.request.method", req.method().as_str());
+            span.set_attribute("url.full", req.uri().to_string());
+            span.set_attribute("server.address", server);
+            if let Some(context) = span.context() {
+                context.inject(req.headers_mut());
+            }
+            span
+        });
+
+        let start = Instant::now();
+        let res = timeout(timeout_duration, self.client.request(req)).await;
+        ctx.stats.record_upstream(server, start.elapsed());
+
+        if let Some(span) = &mut span {
+            match &res {
+                Ok(Ok(res)) => {
+                    span.set_attribute("http.response.status_code", res.status().as_u16());
+                    if res.status().is_server_error() {
+                        span.set_error(res.status().to_string());
+                    }
+                }
+                Ok(Err(e)) => span.set_error(e.to_string()),
+                Err(_) => span.set_error("upstream request timed out"),
+            }
+        }
+
+        match res {
             Ok(Ok(res)) => {
-                let (parts, body) = res.into_parts();
-                let body = GatewayBody::Incomming(body);
-                Response::from_parts(parts, body)
+                metrics().observe_upstream(&self.backend, server, start.elapsed());
+
+                if res.status().is_server_error() {
+                    self.report(server, Outcome::Failure);
+                } else {
+                    self.report(server, Outcome::Success);
+                }
+
+                Ok(res)
             }
             Ok(Err(e)) => {
                 log::warn!("Error proxying request: {}", e);
                 log::debug!("Connection info: {:?}", e.connect_info());
+                self.report(server, Outcome::Failure);
 
-                Response::builder()
-                    .status(StatusCode::BAD_GATEWAY)
-                    .body(GatewayBody::Empty)
-                    .unwrap()
+                let e = UpstreamError::Request(e);
+                metrics().upstream_error(&self.backend, server, e.kind());
+                Err(e)
             }
             Err(e) => {
                 log::warn!("Request timed out: {}", e);
+                self.report(server, Outcome::Failure);
+                metrics().upstream_error(&self.backend, server, "timeout");
+                metrics().timeout("request");
+
+                Err(UpstreamError::Timeout)
+            }
+        }
+    }
+
+    /// Wraps the body of a client request to stream it upstream, failing it at `deadline`.
+    fn streamed(&self, req: Request<Incoming>, deadline: Option<Instant>) -> Request<GatewayBody> {
+        req.map(|body| {
+            let body = GatewayBody::streaming(body, self.timeouts.idle_timeout());
+            match deadline {
+                Some(deadline) => body.with_deadline(deadline, false),
+                None => body,
+            }
+        })
+    }
 
-                Response::builder()
-                    .status(StatusCode::BAD_GATEWAY)
-                    .body(GatewayBody::Empty)
-                    .unwrap()
+    /// Protocol a request asks to switch to, when the backend is reached over HTTP/1.1.
+    fn upgrade_protocol<B>(&self, req: &Request<B>) -> Option<HeaderValue> {
+        match self.protocol {
+            UpstreamProtocol::Http1 | UpstreamProtocol::Auto => {
+                upgrade::requested(req.version(), req.headers())
             }
+            UpstreamProtocol::Http2 | UpstreamProtocol::H2c => None,
         }
     }
+
+    async fn proxy_request(
+        &self,
+        mut req: Request<GatewayBody>,
+        backend: &Arc<SelectedLB>,
+        backend_uri: &Uri,
+        deadline: Option<Instant>,
+        ctx: &RequestContext,
+    ) -> Result<Response<GatewayBody>, GatewayError> {
+        let timeout_duration = self.timeouts.request_timeout();
+
+        let upgrade_protocol = self.upgrade_protocol(&req);
+        let client_upgrade = upgrade_protocol
+            .is_some()
+            .then(|| hyper::upgrade::on(&mut req));
+
+        let (mut parts, body) = req.into_parts();
+        let shutdown = parts.extensions.remove::<Shutdown>();
+        let mut headers = self.upstream_headers(&parts, backend_uri);
+        if let Some(protocol) = upgrade_protocol {
+            upgrade::set_headers(&mut headers, protocol);
+        }
+
+        let new_req = Request::builder()
+            .method(parts.method)
+            .uri(backend_uri)
+            .body(
+                body.counted(ctx.stats.bytes_in.clone())
+                    .scoped(&ctx.request_id),
+            )
+            .map_err(|e| {
+                log::error!("Failed to build request: {}", e);
+                GatewayError::BadUri(e.to_string())
+            });
+
+        let mut new_req = new_req?;
+        *new_req.headers_mut() = headers;
+
+        let mut res = self
+            .send(new_req, &backend.server, timeout_duration, deadline, ctx)
+            .await?;
+
+        match client_upgrade {
+            Some(client_upgrade) if res.status() == StatusCode::SWITCHING_PROTOCOLS => {
+                let upstream_upgrade = hyper::upgrade::on(&mut res);
+                let protocol = res.headers().get(UPGRADE).cloned();
+
+                let mut res = self.gateway_response(res, None).map(|_| GatewayBody::Empty);
+                if let Some(protocol) = protocol {
+                    upgrade::set_headers(res.headers_mut(), protocol);
+                }
+
+                tokio::spawn(request_id::scope(
+                    ctx.request_id.clone(),
+                    upgrade::tunnel(
+                        client_upgrade,
+                        upstream_upgrade,
+                        self.timeouts.tunnel_idle_timeout(),
+                        backend.clone(),
+                        shutdown.as_ref().map(Shutdown::track),
+                    ),
+                ));
+                Ok(res)
+            }
+            _ => Ok(self.gateway_response(res, deadline)),
+        }
+    }
+
+    /// Streams an upstream response to the client. At `deadline`, a gRPC response ends with
+    /// `DEADLINE_EXCEEDED` trailers and any other fails.
+    fn gateway_response(
+        &self,
+        res: Response<Incoming>,
+        deadline: Option<Instant>,
+    ) -> Response<GatewayBody> {
+        let (mut parts, body) = res.into_parts();
+        strip_hop_by_hop(&mut parts.headers);
+        append_via(&mut parts.headers, parts.version);
+
+        let mut body = GatewayBody::streaming(body, self.timeouts.idle_timeout());
+        if let Some(deadline) = deadline {
+            body = body.with_deadline(deadline, grpc::is_grpc(&parts.headers));
+        }
+        Response::from_parts(parts, body)
+    }
+}
+
+enum BufferedBody {
+    /// The whole body: its data and the trailers ending it, if any.
+    Complete(Bytes, Option<HeaderMap>),
+    /// The body exceeded the limit: the data read so far, and the rest of the body.
+    Overflow(Bytes, GatewayBody),
+}
+
+/// Reads `body` until it ends or more than `limit` bytes were read.
+async fn buffer_body(mut body: GatewayBody, limit: usize) -> Result<BufferedBody, BoxError> {
+    let mut buffer = Vec::new();
+    let mut trailers = None;
+
+    while let Some(frame) = body.frame().await {
+        match frame?.into_data() {
+            Ok(data) => {
+                buffer.extend_from_slice(&data);
+                if buffer.len() > limit {
+                    return Ok(BufferedBody::Overflow(Bytes::from(buffer), body));
+                }
+            }
+            Err(frame) => trailers = frame.into_trailers().ok(),
+        }
+    }
+
+    Ok(BufferedBody::Complete(Bytes::from(buffer), trailers))
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PUT | Method::DELETE
+    )
+}
+
+/// Exponential back-off with full jitter, before the retry following `attempt`.
+pub fn backoff(retry: &Retry, attempt: u32) -> Duration {
+    let max = retry
+        .backoff_base_ms
+        .saturating_mul(1u64 << attempt.saturating_sub(1).min(16))
+        .min(retry.backoff_max_ms);
+
+    Duration::from_millis(fastrand::u64(0..=max))
 }
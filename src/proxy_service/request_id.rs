@@ -0,0 +1,79 @@
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::types::RequestIdFormat;
+
+tokio::task_local! {
+    static REQUEST_ID: Arc<str>;
+}
+
+const CROCKFORD: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Longest incoming request id that is accepted from a trusted proxy.
+const MAX_LENGTH: usize = 128;
+
+pub fn generate(format: RequestIdFormat) -> String {
+    match format {
+        RequestIdFormat::Uuid => uuid_v4(),
+        RequestIdFormat::Ulid => ulid(),
+    }
+}
+
+/// Whether an incoming request id is safe to reuse in headers and logs.
+pub fn is_valid(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_LENGTH && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Runs `f` with `id` as the request id of every log record it emits.
+pub async fn scope<F: Future>(id: String, f: F) -> F::Output {
+    REQUEST_ID.scope(Arc::from(id), f).await
+}
+
+/// Runs `f` with `id` as the request id, for work done outside of the request's task such as
+/// polling its bodies.
+pub fn sync_scope<R>(id: &Arc<str>, f: impl FnOnce() -> R) -> R {
+    REQUEST_ID.sync_scope(id.clone(), f)
+}
+
+/// Request id of the request handled by the current task, if any.
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.to_string()).ok()
+}
+
+fn uuid_v4() -> String {
+    let mut bytes = fastrand::u128(..).to_be_bytes();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex = bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// 48 bit millisecond timestamp followed by 80 random bits, in Crockford's base32.
+fn ulid() -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis())
+        .unwrap_or_default();
+
+    let value = (millis & 0xffff_ffff_ffff) << 80 | fastrand::u128(..) >> 48;
+
+    (0..26)
+        .rev()
+        .map(|i| CROCKFORD[((value >> (i * 5)) & 0x1f) as usize] as char)
+        .collect()
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+
+/// Routing table compiled once at startup. Routes are indexed by host and then by path segment,
+/// so a lookup only visits the routes that can match the request.
+pub struct Router<T> {
+    routes: Vec<Route<T>>,
+    exact_hosts: HashMap<String, PathTree>,
+    wildcard_hosts: HashMap<String, PathTree>,
+    any_host: PathTree,
+}
+
+struct Route<T> {
+    priority: i32,
+    predicates: usize,
+    value: T,
+}
+
+#[derive(Default)]
+struct PathTree {
+    root: Node,
+}
+
+#[derive(Default)]
+struct Node {
+    children: HashMap<String, Node>,
+    exact: Vec<usize>,
+    prefix: Vec<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum HostMatch {
+    Any,
+    Wildcard(usize),
+    Exact,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum PathMatch {
+    Prefix(usize),
+    Exact,
+}
+
+struct Candidate {
+    route: usize,
+    host: HostMatch,
+    path: PathMatch,
+}
+
+impl PathTree {
+    fn insert(&mut self, pattern: &str, route: usize) {
+        let (path, is_prefix) = match pattern.strip_suffix("/*") {
+            Some(path) => (path, true),
+            None => (pattern, false),
+        };
+
+        let mut node = &mut self.root;
+        for segment in segments(path) {
+            node = node.children.entry(segment.to_string()).or_default();
+        }
+
+        if is_prefix {
+            node.prefix.push(route);
+        } else {
+            node.exact.push(route);
+        }
+    }
+
+    fn collect(&self, path: &str, host: HostMatch, candidates: &mut Vec<Candidate>) {
+        let segments = segments(path).collect::<Vec<_>>();
+        let mut node = &self.root;
+
+        for (depth, segment) in segments.iter().enumerate() {
+            candidates.extend(node.prefix.iter().map(|&route| Candidate {
+                route,
+                host,
+                path: PathMatch::Prefix(depth),
+            }));
+
+            match node.children.get(*segment) {
+                Some(child) => node = child,
+                None => return,
+            }
+        }
+
+        candidates.extend(node.exact.iter().map(|&route| Candidate {
+            route,
+            host,
+            path: PathMatch::Exact,
+        }));
+    }
+}
+
+impl<T> Router<T> {
+    pub fn new() -> Self {
+        Self {
+            routes: Vec::new(),
+            exact_hosts: HashMap::new(),
+            wildcard_hosts: HashMap::new(),
+            any_host: PathTree::default(),
+        }
+    }
+
+    /// Adds a route. An empty `hosts` list matches any host and an empty `paths` list matches
+    /// any path. `predicates` is the number of extra conditions the route checks, a route with
+    /// more of them is more specific.
+    pub fn insert(
+        &mut self,
+        hosts: &[String],
+        paths: &[String],
+        priority: i32,
+        predicates: usize,
+        value: T,
+    ) {
+        let route = self.routes.len();
+        self.routes.push(Route {
+            priority,
+            predicates,
+            value,
+        });
+
+        let any_path = ["/*".to_string()];
+        let paths = if paths.is_empty() {
+            &any_path[..]
+        } else {
+            paths
+        };
+
+        if hosts.is_empty() {
+            for path in paths {
+                self.any_host.insert(path, route);
+            }
+        }
+
+        for host in hosts {
+            let host = normalize_host(host);
+            let tree = match host.strip_prefix("*.") {
+                Some(suffix) => self.wildcard_hosts.entry(suffix.to_string()).or_default(),
+                None => self.exact_hosts.entry(host).or_default(),
+            };
+
+            for path in paths {
+                tree.insert(path, route);
+            }
+        }
+    }
+
+    /// Finds the most specific route accepted by `filter`. Routes are ordered by priority, then
+    /// by host specificity, then by path specificity, then by number of predicates, then by
+    /// insertion order.
+    pub fn route(&self, host: Option<&str>, path: &str, filter: impl Fn(&T) -> bool) -> Option<&T> {
+        let mut candidates = Vec::new();
+
+        if let Some(host) = host.map(normalize_host) {
+            if let Some(tree) = self.exact_hosts.get(&host) {
+                tree.collect(path, HostMatch::Exact, &mut candidates);
+            }
+
+            for (dot, _) in host.match_indices('.') {
+                let suffix = &host[dot + 1..];
+                if let Some(tree) = self.wildcard_hosts.get(suffix) {
+                    tree.collect(path, HostMatch::Wildcard(suffix.len()), &mut candidates);
+                }
+            }
+        }
+
+        self.any_host.collect(path, HostMatch::Any, &mut candidates);
+
+        candidates
+            .into_iter()
+            .filter(|candidate| filter(&self.routes[candidate.route].value))
+            .max_by(|a, b| {
+                let a_route = &self.routes[a.route];
+                let b_route = &self.routes[b.route];
+
+                a_route
+                    .priority
+                    .cmp(&b_route.priority)
+                    .then(a.host.cmp(&b.host))
+                    .then(a.path.cmp(&b.path))
+                    .then(a_route.predicates.cmp(&b_route.predicates))
+                    .then(b.route.cmp(&a.route))
+            })
+            .map(|candidate| &self.routes[candidate.route].value)
+    }
+
+    pub fn lookup(&self, host: Option<&str>, path: &str) -> Option<&T> {
+        self.route(host, path, |_| true)
+    }
+}
+
+impl<T> Default for Router<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+    path.strip_prefix('/')
+        .unwrap_or(path)
+        .split('/')
+        .filter(|_| !path.is_empty())
+}
+
+/// Lower-cases a host and strips its port and trailing dot.
+fn normalize_host(host: &str) -> String {
+    let host = match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or(rest),
+        None => host.split(':').next().unwrap_or(host),
+    };
+
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
@@ -0,0 +1,63 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum TemplateError {
+    Unterminated(String),
+    UnknownPlaceholder { name: String, template: String },
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::Unterminated(template) => {
+                write!(f, "unterminated placeholder in {:?}", template)
+            }
+            TemplateError::UnknownPlaceholder { name, template } => {
+                write!(f, "unknown placeholder ${{{}}} in {:?}", name, template)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// A piece of a template: text copied as is, or a `${name}` placeholder.
+pub enum Part<T> {
+    Literal(String),
+    Placeholder(T),
+}
+
+/// Splits a template into literals and `${name}` placeholders, the names being looked up with
+/// `placeholder`.
+pub fn parse<T>(
+    template: &str,
+    placeholder: impl Fn(&str) -> Option<T>,
+) -> Result<Vec<Part<T>>, TemplateError> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("${") {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| TemplateError::Unterminated(template.to_string()))?;
+
+        if start > 0 {
+            parts.push(Part::Literal(rest[..start].to_string()));
+        }
+
+        let name = &rest[start + 2..start + end];
+        let value = placeholder(name).ok_or_else(|| TemplateError::UnknownPlaceholder {
+            name: name.to_string(),
+            template: template.to_string(),
+        })?;
+        parts.push(Part::Placeholder(value));
+
+        rest = &rest[start + end + 1..];
+    }
+
+    if !rest.is_empty() {
+        parts.push(Part::Literal(rest.to_string()));
+    }
+
+    Ok(parts)
+}
@@ -0,0 +1,143 @@
+use std::{io, sync::Arc, time::Duration};
+
+use hyper::{
+    header::{HeaderValue, CONNECTION, UPGRADE},
+    upgrade::OnUpgrade,
+    HeaderMap, Version,
+};
+use hyper_util::rt::TokioIo;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    time::timeout,
+};
+
+use crate::{
+    load_balancer::factory::SelectedLB, metrics::metrics, server::shutdown::ConnectionGuard,
+};
+
+/// Size of the buffer of each direction of a tunnel.
+const BUFFER_SIZE: usize = 16 * 1024;
+
+/// Protocol an HTTP/1.1 request asks to switch to, e.g. `websocket`. `h2c` is left to the
+/// listener, as it only concerns the client connection.
+pub fn requested(version: Version, headers: &HeaderMap) -> Option<HeaderValue> {
+    if version != Version::HTTP_11 {
+        return None;
+    }
+
+    let connection_upgrade = headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|option| option.trim().eq_ignore_ascii_case("upgrade"));
+
+    let protocol = headers.get(UPGRADE)?;
+    let h2c = protocol
+        .to_str()
+        .is_ok_and(|protocol| protocol.to_ascii_lowercase().contains("h2c"));
+
+    if connection_upgrade && !h2c {
+        Some(protocol.clone())
+    } else {
+        None
+    }
+}
+
+/// Adds back the `Connection` and `Upgrade` headers removed as hop-by-hop.
+pub fn set_headers(headers: &mut HeaderMap, protocol: HeaderValue) {
+    headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+    headers.insert(UPGRADE, protocol);
+}
+
+/// Splices the client and upstream connections once both are upgraded. `server` is held until
+/// the tunnel closes, so the session counts as a connection of the server, and so is
+/// `connection`, so the shutdown drain waits for it.
+pub async fn tunnel(
+    client: OnUpgrade,
+    upstream: OnUpgrade,
+    idle_timeout: Duration,
+    server: Arc<SelectedLB>,
+    connection: Option<ConnectionGuard>,
+) {
+    let _connection = connection;
+
+    let (client, upstream) = match tokio::try_join!(client, upstream) {
+        Ok(upgraded) => upgraded,
+        Err(e) => {
+            log::warn!("Failed to upgrade connection to {}: {}", server.server, e);
+            return;
+        }
+    };
+
+    let _tunnel = metrics().tunnel_opened();
+    log::debug!("Tunnel to {} opened", server.server);
+
+    match copy_bidirectional(TokioIo::new(client), TokioIo::new(upstream), idle_timeout).await {
+        Ok((sent, received)) => log::debug!(
+            "Tunnel to {} closed after sending {} and receiving {} bytes",
+            server.server,
+            sent,
+            received
+        ),
+        Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+            log::debug!(
+                "Tunnel to {} idle for {:?}, closing",
+                server.server,
+                idle_timeout
+            );
+            metrics().timeout("tunnel_idle");
+        }
+        Err(e) => log::debug!("Tunnel to {} failed: {}", server.server, e),
+    }
+}
+
+/// Copies both directions until both ends are closed, failing with `TimedOut` when nothing is
+/// read or written for `idle_timeout`. Returns the bytes sent to `b` and to `a`.
+pub async fn copy_bidirectional<A, B>(a: A, b: B, idle_timeout: Duration) -> io::Result<(u64, u64)>
+where
+    A: AsyncRead + AsyncWrite,
+    B: AsyncRead + AsyncWrite,
+{
+    let (mut a_read, mut a_write) = tokio::io::split(a);
+    let (mut b_read, mut b_write) = tokio::io::split(b);
+    let mut a_buf = vec![0; BUFFER_SIZE];
+    let mut b_buf = vec![0; BUFFER_SIZE];
+    let (mut a_open, mut b_open) = (true, true);
+    let (mut to_b, mut to_a) = (0, 0);
+
+    while a_open || b_open {
+        let step = timeout(idle_timeout, async {
+            tokio::select! {
+                read = a_read.read(&mut a_buf), if a_open => {
+                    forward(&a_buf[..read?], &mut b_write).await.map(|n| (true, n))
+                }
+                read = b_read.read(&mut b_buf), if b_open => {
+                    forward(&b_buf[..read?], &mut a_write).await.map(|n| (false, n))
+                }
+            }
+        });
+
+        match step.await {
+            Err(_) => return Err(io::ErrorKind::TimedOut.into()),
+            Ok(step) => match step? {
+                (true, 0) => a_open = false,
+                (true, n) => to_b += n as u64,
+                (false, 0) => b_open = false,
+                (false, n) => to_a += n as u64,
+            },
+        }
+    }
+
+    Ok((to_b, to_a))
+}
+
+/// Writes `data` to `writer`, or shuts its write side down at the end of the stream.
+async fn forward<W: AsyncWrite + Unpin>(data: &[u8], writer: &mut W) -> io::Result<usize> {
+    if data.is_empty() {
+        writer.shutdown().await?;
+    } else {
+        writer.write_all(data).await?;
+    }
+    Ok(data.len())
+}
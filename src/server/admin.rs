@@ -0,0 +1,403 @@
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::{
+    body::{Bytes, Incoming},
+    header::{HeaderValue, CONTENT_TYPE},
+    server::conn::http1,
+    service::service_fn,
+    Method, Request, Response, StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::{net::TcpListener, time};
+
+use crate::{
+    gateway::{Gateway, Generation},
+    load_balancer::factory::LoadBalancer,
+    metrics::metrics,
+    proxy_service::{backend_url::BackendUrl, gateway_body::GatewayBody},
+    types::{Backend, BackendServer, LbAlgorithm},
+};
+
+use super::shutdown::Shutdown;
+
+/// Largest request body accepted by the admin API.
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// What the admin endpoints report on.
+pub struct AdminState {
+    gateway: Arc<Gateway>,
+    shutdown: Shutdown,
+    started: Instant,
+    started_at: SystemTime,
+}
+
+impl AdminState {
+    pub fn new(gateway: Arc<Gateway>, shutdown: Shutdown) -> Self {
+        Self {
+            gateway,
+            shutdown,
+            started: Instant::now(),
+            started_at: SystemTime::now(),
+        }
+    }
+}
+
+/// Body of the requests changing the servers of a backend.
+#[derive(Deserialize)]
+struct ServerChange {
+    server: String,
+    weight: Option<u32>,
+    drain: Option<bool>,
+}
+
+/// Serves the operational endpoints on their own listener, so they are never exposed through a
+/// frontend.
+pub async fn start_admin_server(
+    address: SocketAddr,
+    state: Arc<AdminState>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tcp_listener = TcpListener::bind(&address).await?;
+
+    log::info!("Admin listener started on {}", address);
+
+    serve_admin(tcp_listener, state).await
+}
+
+/// Serves the admin endpoints on an already bound listener.
+pub async fn serve_admin(
+    tcp_listener: TcpListener,
+    state: Arc<AdminState>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    loop {
+        match tcp_listener.accept().await {
+            Ok((stream, _)) => {
+                let io = TokioIo::new(stream);
+                let state = state.clone();
+                let service = service_fn(move |req| {
+                    let state = state.clone();
+                    async move { Ok::<_, hyper::Error>(handle(req, &state).await) }
+                });
+
+                tokio::spawn(async move {
+                    if let Err(err) = http1::Builder::new().serve_connection(io, service).await {
+                        log::debug!("Failed to serve admin connection: {:?}", err);
+                    }
+                });
+            }
+            Err(e) => {
+                log::warn!("Failed to accept admin connection {:?}", e);
+
+                time::sleep(time::Duration::from_millis(10)).await;
+            }
+        }
+    }
+}
+
+async fn handle(req: Request<Incoming>, state: &AdminState) -> Response<GatewayBody> {
+    if let Some(backend) = req
+        .uri()
+        .path()
+        .strip_prefix("/backends/")
+        .and_then(|rest| rest.strip_suffix("/servers"))
+    {
+        let backend = backend.to_string();
+        return match *req.method() {
+            Method::POST | Method::PATCH | Method::DELETE => {
+                update_servers(req, state, &backend).await
+            }
+            _ => error(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed"),
+        };
+    }
+
+    match (req.method(), req.uri().path()) {
+        (&Method::GET, "/metrics") => text(
+            StatusCode::OK,
+            "text/plain; version=0.0.4; charset=utf-8",
+            metrics().render(),
+        ),
+        (&Method::GET, "/ready") => {
+            if state.shutdown.is_ready() {
+                text(
+                    StatusCode::OK,
+                    "text/plain; charset=utf-8",
+                    "ready\n".to_string(),
+                )
+            } else {
+                text(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "text/plain; charset=utf-8",
+                    "shutting down\n".to_string(),
+                )
+            }
+        }
+        (&Method::GET, "/config") => match serde_json::to_value(&state.gateway.current().config) {
+            Ok(config) => json_response(StatusCode::OK, config),
+            Err(e) => json_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                json!({ "error": e.to_string() }),
+            ),
+        },
+        (&Method::GET, "/routes") => {
+            json_response(StatusCode::OK, routes(&state.gateway.current()))
+        }
+        (&Method::GET, "/backends") => {
+            json_response(StatusCode::OK, backends(&state.gateway.current()))
+        }
+        (&Method::GET, "/version") => json_response(StatusCode::OK, version(state)),
+        _ => text(
+            StatusCode::NOT_FOUND,
+            "text/plain; charset=utf-8",
+            "Not Found\n".to_string(),
+        ),
+    }
+}
+
+/// The frontends as the router sees them, with the timeouts that apply after falling back to the
+/// backend's.
+fn routes(generation: &Generation) -> Value {
+    let routes = generation
+        .config
+        .frontends
+        .iter()
+        .enumerate()
+        .map(|(index, frontend)| {
+            let backend_timeouts = generation
+                .config
+                .backends
+                .iter()
+                .find(|backend| backend.name == frontend.backend)
+                .map(|backend| backend.timeouts.clone())
+                .unwrap_or_default();
+
+            json!({
+                "index": index,
+                "name": frontend.name,
+                "hosts": frontend.hosts,
+                "path_prefixes": frontend.path_prefix,
+                "grpc_services": frontend.grpc_services,
+                "priority": frontend.priority,
+                "match": frontend.predicates,
+                "backend": frontend.backend,
+                "strip_prefix": frontend.strip_prefix,
+                "add_prefix": frontend.add_prefix,
+                "rewrite": frontend.rewrite,
+                "timeouts": frontend.timeouts.or(&backend_timeouts),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Value::Array(routes)
+}
+
+/// Every backend with the health and active connections of its servers.
+fn backends(generation: &Generation) -> Value {
+    let backends = generation
+        .config
+        .backends
+        .iter()
+        .filter_map(|backend| Some(backend_status(backend, generation.balancer(&backend.name)?)))
+        .collect::<Vec<_>>();
+
+    Value::Array(backends)
+}
+
+/// The live servers of a backend, which differ from the config after changes through the API.
+fn backend_status(backend: &Backend, balancer: &Arc<dyn LoadBalancer>) -> Value {
+    let health = balancer.health();
+    let connections = balancer.connections();
+
+    let servers = balancer
+        .servers()
+        .into_iter()
+        .map(|server| {
+            let server_health = health.get(&server.server);
+
+            json!({
+                "server": server.server,
+                "weight": server.weight.unwrap_or(1),
+                "healthy": server_health.as_ref().is_none_or(|health| health.is_healthy()),
+                "ejected": server_health.as_ref().is_some_and(|health| health.is_ejected()),
+                "draining": server_health.as_ref().is_some_and(|health| health.is_draining()),
+                "active_connections": connections
+                    .iter()
+                    .find(|(name, _)| *name == server.server)
+                    .map(|(_, connections)| *connections),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "name": backend.name,
+        "lb_algorithm": backend.lb_algorithm,
+        "protocol": backend.protocol,
+        "servers": servers,
+    })
+}
+
+/// Adds (`POST`), re-weights or drains (`PATCH`) and removes (`DELETE`) a server of a backend.
+async fn update_servers(
+    req: Request<Incoming>,
+    state: &AdminState,
+    backend: &str,
+) -> Response<GatewayBody> {
+    let method = req.method().clone();
+
+    let change = match Limited::new(req.into_body(), MAX_BODY_BYTES)
+        .collect()
+        .await
+    {
+        Ok(body) => serde_json::from_slice::<ServerChange>(&body.to_bytes()),
+        Err(e) => return error(StatusCode::BAD_REQUEST, &e.to_string()),
+    };
+    let change = match change {
+        Ok(change) => change,
+        Err(e) => return error(StatusCode::BAD_REQUEST, &format!("Invalid body: {}", e)),
+    };
+
+    if change.weight == Some(0) {
+        return error(StatusCode::BAD_REQUEST, "weight must be at least 1");
+    }
+    if method == Method::PATCH && change.weight.is_none() && change.drain.is_none() {
+        return error(StatusCode::BAD_REQUEST, "weight or drain must be set");
+    }
+
+    // Held until the change is applied, so a reload cannot replace the balancer meanwhile.
+    let _servers = state.gateway.lock_servers();
+    let generation = state.gateway.current();
+    let (Some(config), Some(balancer)) = (
+        generation
+            .config
+            .backends
+            .iter()
+            .find(|b| b.name == backend),
+        generation.balancer(backend),
+    ) else {
+        return error(
+            StatusCode::NOT_FOUND,
+            &format!("Unknown backend {:?}", backend),
+        );
+    };
+
+    if method == Method::PATCH
+        && change.weight.is_some()
+        && config.lb_algorithm != LbAlgorithm::WeightedRoundRobin
+    {
+        return error(
+            StatusCode::BAD_REQUEST,
+            &format!(
+                "weight only applies to WeightedRoundRobin, backend {:?} uses {:?}",
+                backend, config.lb_algorithm
+            ),
+        );
+    }
+
+    let mut servers = balancer.servers();
+    let position = servers
+        .iter()
+        .position(|server| server.server == change.server);
+
+    match (&method, position) {
+        (&Method::POST, Some(_)) => {
+            return error(
+                StatusCode::CONFLICT,
+                &format!("Server {:?} already exists", change.server),
+            );
+        }
+        (&Method::POST, None) => {
+            if let Err(e) = BackendUrl::parse(&change.server)
+                .and_then(|url| url.check_protocol(config.protocol))
+            {
+                return error(
+                    StatusCode::BAD_REQUEST,
+                    &format!("Invalid server {:?}: {}", change.server, e),
+                );
+            }
+
+            servers.push(BackendServer {
+                server: change.server.clone(),
+                weight: change.weight,
+            });
+            balancer.set_servers(servers);
+            log::info!("Added server {} to backend {}", change.server, backend);
+        }
+        (&Method::PATCH, Some(position)) => {
+            if let Some(weight) = change.weight {
+                servers[position].weight = Some(weight);
+                balancer.set_servers(servers);
+                log::info!(
+                    "Set weight of server {} in backend {} to {}",
+                    change.server,
+                    backend,
+                    weight
+                );
+            }
+
+            if let (Some(drain), Some(health)) =
+                (change.drain, balancer.health().get(&change.server))
+            {
+                health.set_draining(drain);
+                log::info!(
+                    "{} server {} in backend {}",
+                    if drain { "Draining" } else { "Undrained" },
+                    change.server,
+                    backend
+                );
+            }
+        }
+        (&Method::DELETE, Some(_)) if servers.len() == 1 => {
+            return error(
+                StatusCode::CONFLICT,
+                &format!("Cannot remove the last server of backend {:?}", backend),
+            );
+        }
+        (&Method::DELETE, Some(position)) => {
+            servers.remove(position);
+            balancer.set_servers(servers);
+            log::info!("Removed server {} from backend {}", change.server, backend);
+        }
+        _ => {
+            return error(
+                StatusCode::NOT_FOUND,
+                &format!("Unknown server {:?}", change.server),
+            );
+        }
+    }
+
+    json_response(StatusCode::OK, backend_status(config, balancer))
+}
+
+fn version(state: &AdminState) -> Value {
+    json!({
+        "name": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        "started_at": state
+            .started_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        "uptime_seconds": state.started.elapsed().as_secs(),
+    })
+}
+
+fn error(status: StatusCode, message: &str) -> Response<GatewayBody> {
+    json_response(status, json!({ "error": message }))
+}
+
+fn json_response(status: StatusCode, value: Value) -> Response<GatewayBody> {
+    text(status, "application/json", format!("{}\n", value))
+}
+
+fn text(status: StatusCode, content_type: &'static str, body: String) -> Response<GatewayBody> {
+    let mut res = Response::new(GatewayBody::Buffered(Full::new(Bytes::from(body))));
+    *res.status_mut() = status;
+    res.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+    res
+}
@@ -0,0 +1,266 @@
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use hyper::{
+    body::{Body, Incoming},
+    header::{GetAll, HeaderName, HeaderValue, CONNECTION, HOST, UPGRADE},
+    upgrade::Upgraded,
+    Request, Response, StatusCode, Version,
+};
+use hyper_util::rt::TokioIo;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf},
+    time::timeout,
+};
+
+use crate::proxy_service::gateway_body::GatewayBody;
+
+/// Sent by the client first on an HTTP/2 connection.
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// How long the client may take to send its preface after the switch.
+const PREFACE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Every HTTP/2 endpoint accepts frames of this size.
+const MAX_FRAME_SIZE: usize = 16_384;
+
+const FRAME_HEADERS: u8 = 0x1;
+const FRAME_SETTINGS: u8 = 0x4;
+const FRAME_CONTINUATION: u8 = 0x9;
+const FLAG_END_STREAM: u8 = 0x1;
+const FLAG_END_HEADERS: u8 = 0x4;
+
+const HTTP2_SETTINGS: HeaderName = HeaderName::from_static("http2-settings");
+
+/// Connection-specific headers, which HTTP/2 does not allow.
+const CONNECTION_HEADERS: [&str; 6] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+    "http2-settings",
+];
+
+/// The connection switched to HTTP/2, replaying the client preface read to answer the request
+/// that asked for the switch.
+pub type H2cIo = TokioIo<Rewind>;
+
+/// Whether an HTTP/1.1 request asks to switch its connection to HTTP/2 (RFC 7540, section 3.2).
+/// Requests with a body are answered over HTTP/1.1, as the body would have to be read before the
+/// switch.
+pub fn requested(req: &Request<Incoming>) -> bool {
+    if req.version() != Version::HTTP_11 || !req.body().is_end_stream() {
+        return false;
+    }
+
+    let h2c = tokens(req.headers().get_all(UPGRADE)).any(|token| token == "h2c");
+    let connection_upgrade =
+        tokens(req.headers().get_all(CONNECTION)).any(|token| token == "upgrade");
+    let settings = req.headers().get_all(HTTP2_SETTINGS).iter().count() == 1;
+
+    h2c && connection_upgrade && settings
+}
+
+/// Answers `101 Switching Protocols` and, once the client sent its HTTP/2 preface, hands the
+/// connection to `serve`, which answers `req` on stream 1.
+pub fn upgrade<F, Fut>(mut req: Request<Incoming>, serve: F) -> Response<GatewayBody>
+where
+    F: FnOnce(H2cIo) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let on_upgrade = hyper::upgrade::on(&mut req);
+    let headers = header_block(&req);
+
+    tokio::spawn(async move {
+        let upgraded = match on_upgrade.await {
+            Ok(upgraded) => TokioIo::new(upgraded),
+            Err(e) => {
+                log::debug!("Failed to switch to h2c: {}", e);
+                return;
+            }
+        };
+
+        match timeout(PREFACE_TIMEOUT, read_preface(upgraded, &headers)).await {
+            Ok(Ok(io)) => serve(TokioIo::new(io)).await,
+            Ok(Err(e)) => log::debug!("Invalid HTTP/2 preface after switching to h2c: {}", e),
+            Err(_) => log::debug!("No HTTP/2 preface after switching to h2c"),
+        }
+    });
+
+    let mut res = Response::new(GatewayBody::Empty);
+    *res.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+    res.headers_mut()
+        .insert(CONNECTION, HeaderValue::from_static("upgrade"));
+    res.headers_mut()
+        .insert(UPGRADE, HeaderValue::from_static("h2c"));
+    res
+}
+
+/// Reads the client preface and its SETTINGS frame, and replays them followed by `headers`, so
+/// the request that asked for the switch is received as stream 1.
+async fn read_preface(mut io: TokioIo<Upgraded>, headers: &[u8]) -> io::Result<Rewind> {
+    let mut preface = vec![0; PREFACE.len() + 9];
+    io.read_exact(&mut preface).await?;
+
+    let frame = &preface[PREFACE.len()..];
+    let length = u32::from_be_bytes([0, frame[0], frame[1], frame[2]]) as usize;
+    if !preface.starts_with(PREFACE) || frame[3] != FRAME_SETTINGS || length > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "expected the connection preface",
+        ));
+    }
+
+    let mut settings = vec![0; length];
+    io.read_exact(&mut settings).await?;
+
+    preface.extend_from_slice(&settings);
+    preface.extend_from_slice(headers);
+
+    Ok(Rewind {
+        prefix: preface,
+        position: 0,
+        inner: io,
+    })
+}
+
+/// The HEADERS and CONTINUATION frames carrying `req` on stream 1.
+fn header_block(req: &Request<Incoming>) -> Vec<u8> {
+    let authority = req
+        .headers()
+        .get(HOST)
+        .map(|host| host.as_bytes())
+        .or_else(|| {
+            req.uri()
+                .authority()
+                .map(|authority| authority.as_str().as_bytes())
+        })
+        .unwrap_or_default();
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or("/");
+
+    // Headers named in `Connection` are specific to the connection too.
+    let connection = tokens(req.headers().get_all(CONNECTION)).collect::<Vec<_>>();
+
+    let mut block = Vec::new();
+    literal(&mut block, b":method", req.method().as_str().as_bytes());
+    literal(&mut block, b":scheme", b"http");
+    literal(&mut block, b":authority", authority);
+    literal(&mut block, b":path", path.as_bytes());
+
+    for (name, value) in req.headers() {
+        if name == HOST
+            || CONNECTION_HEADERS.contains(&name.as_str())
+            || connection.iter().any(|token| token == name.as_str())
+            || (name == "te" && value != "trailers")
+        {
+            continue;
+        }
+        literal(&mut block, name.as_str().as_bytes(), value.as_bytes());
+    }
+
+    let mut frames = Vec::with_capacity(block.len() + 9);
+    let chunks = block.chunks(MAX_FRAME_SIZE).collect::<Vec<_>>();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let (kind, mut flags) = match index {
+            0 => (FRAME_HEADERS, FLAG_END_STREAM),
+            _ => (FRAME_CONTINUATION, 0),
+        };
+        if index == chunks.len() - 1 {
+            flags |= FLAG_END_HEADERS;
+        }
+
+        frames.extend_from_slice(&(chunk.len() as u32).to_be_bytes()[1..]);
+        frames.extend_from_slice(&[kind, flags]);
+        frames.extend_from_slice(&1u32.to_be_bytes());
+        frames.extend_from_slice(chunk);
+    }
+    frames
+}
+
+/// Encodes a header as a literal without indexing (RFC 7541, section 6.2.2), which leaves the
+/// decoder's dynamic table untouched.
+fn literal(block: &mut Vec<u8>, name: &[u8], value: &[u8]) {
+    block.push(0);
+    for string in [name, value] {
+        integer(block, string.len(), 7);
+        block.extend_from_slice(string);
+    }
+}
+
+/// Encodes an integer with a `prefix`-bit prefix (RFC 7541, section 5.1).
+fn integer(block: &mut Vec<u8>, mut value: usize, prefix: u32) {
+    let max = (1 << prefix) - 1;
+    if value < max {
+        block.push(value as u8);
+        return;
+    }
+
+    block.push(max as u8);
+    value -= max;
+    while value >= 128 {
+        block.push((value % 128 + 128) as u8);
+        value /= 128;
+    }
+    block.push(value as u8);
+}
+
+/// The lowercased comma-separated tokens of a header.
+fn tokens(values: GetAll<'_, HeaderValue>) -> impl Iterator<Item = String> + '_ {
+    values
+        .into_iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|token| token.trim().to_ascii_lowercase())
+}
+
+/// Reads `prefix` before the rest of `inner`.
+pub struct Rewind {
+    prefix: Vec<u8>,
+    position: usize,
+    inner: TokioIo<Upgraded>,
+}
+
+impl AsyncRead for Rewind {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.position < self.prefix.len() {
+            let n = buf.remaining().min(self.prefix.len() - self.position);
+            buf.put_slice(&self.prefix[self.position..self.position + n]);
+            self.position += n;
+            return Poll::Ready(Ok(()));
+        }
+
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Rewind {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
@@ -1,30 +1,95 @@
-use std::{net::SocketAddr, sync::Arc};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
-use hyper::{body::Incoming, server::conn::http1, service::service_fn, Request, Response};
-use hyper_util::rt::TokioIo;
+use hyper::{body::Incoming, service::service_fn, Request, Response};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo, TokioTimer},
+    server::conn::auto,
+};
 use tokio::{net::TcpListener, time};
 
-use crate::proxy_service::{gateway_body::GatewayBody, proxy_bridge::ProxyBridge};
+use crate::{
+    gateway::Gateway, metrics::metrics, proxy_service::gateway_body::GatewayBody,
+    types::Http2Settings,
+};
+
+use super::{h2c, shutdown::Shutdown};
 
 pub async fn start_http_server(
     address: SocketAddr,
-    proxy_bridge: &Arc<ProxyBridge>,
+    gateway: &Arc<Gateway>,
+    shutdown: &Shutdown,
+    http2: &Http2Settings,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let tcp_listener = TcpListener::bind(&address).await?;
+    serve_http(tcp_listener, gateway, shutdown, http2).await
+}
+
+/// Accepts connections on `tcp_listener` until the shutdown is triggered.
+pub async fn serve_http(
+    tcp_listener: TcpListener,
+    gateway: &Arc<Gateway>,
+    shutdown: &Shutdown,
+    http2: &Http2Settings,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let address = tcp_listener.local_addr()?;
+    let builder = Arc::new(connection_builder(http2));
+    let h2c_builder = Arc::new(connection_builder(http2).http2_only());
 
     loop {
-        match tcp_listener.accept().await {
-            Ok((stream, _)) => {
+        let accepted = tokio::select! {
+            accepted = tcp_listener.accept() => accepted,
+            _ = shutdown.triggered() => {
+                log::info!("Stopped accepting connections on {}", address);
+                return Ok(());
+            }
+        };
+
+        match accepted {
+            Ok((stream, peer)) => {
+                metrics().connection_accepted("http");
+
                 stream.set_nodelay(true)?;
 
                 let io = TokioIo::new(stream);
 
-                let proxy_bridge = proxy_bridge.clone();
-                let service = Arc::new(service_fn(move |req| wrapper(req, proxy_bridge.clone())));
+                let service = {
+                    let gateway = gateway.clone();
+                    let shutdown = shutdown.clone();
+                    let h2c_builder = h2c_builder.clone();
+                    Arc::new(service_fn(move |req| {
+                        wrapper(
+                            req,
+                            peer,
+                            gateway.clone(),
+                            shutdown.clone(),
+                            h2c_builder.clone(),
+                        )
+                    }))
+                };
+
+                let builder = builder.clone();
+                let shutdown = shutdown.clone();
+                let connection = shutdown.track();
 
                 tokio::spawn(async move {
-                    if let Err(err) = http1::Builder::new().serve_connection(io, service).await {
-                        println!("Failed to serve the connection: {:?}", err);
+                    let _connection = connection;
+
+                    // Serves HTTP/1.1 and, from the connection preface, HTTP/2 with prior
+                    // knowledge (h2c). Upgraded connections are handed over to the proxy, or
+                    // served as HTTP/2 after `Upgrade: h2c`.
+                    let conn = builder.serve_connection_with_upgrades(io, service);
+                    tokio::pin!(conn);
+
+                    let result = tokio::select! {
+                        result = conn.as_mut() => result,
+                        _ = shutdown.triggered() => {
+                            conn.as_mut().graceful_shutdown();
+                            conn.await
+                        }
+                    };
+
+                    if let Err(err) = result {
+                        log::debug!("Failed to serve the connection: {:?}", err);
                     }
                 });
             }
@@ -39,9 +104,76 @@ pub async fn start_http_server(
     }
 }
 
+/// Detects HTTP/1.1 or HTTP/2 from the first bytes of a connection.
+pub fn connection_builder(http2: &Http2Settings) -> auto::Builder<TokioExecutor> {
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+
+    let mut http2_builder = builder.http2();
+    http2_builder
+        .timer(TokioTimer::new())
+        .initial_stream_window_size(http2.initial_stream_window_size)
+        .initial_connection_window_size(http2.initial_connection_window_size)
+        .adaptive_window(http2.adaptive_window)
+        .max_frame_size(http2.max_frame_size)
+        .keep_alive_interval(http2.keep_alive_interval_ms.map(Duration::from_millis));
+
+    if let Some(max_concurrent_streams) = http2.max_concurrent_streams {
+        http2_builder.max_concurrent_streams(max_concurrent_streams);
+    }
+    if let Some(keep_alive_timeout_ms) = http2.keep_alive_timeout_ms {
+        http2_builder.keep_alive_timeout(Duration::from_millis(keep_alive_timeout_ms));
+    }
+
+    builder
+}
+
 async fn wrapper(
     req: Request<Incoming>,
-    proxy_bridge: Arc<ProxyBridge>,
+    peer: SocketAddr,
+    gateway: Arc<Gateway>,
+    shutdown: Shutdown,
+    h2c_builder: Arc<auto::Builder<TokioExecutor>>,
+) -> Result<Response<GatewayBody>, hyper::Error> {
+    if !h2c::requested(&req) {
+        return proxy(req, peer, gateway, shutdown).await;
+    }
+
+    // The switched connection outlives the HTTP/1.1 one, so it is tracked on its own.
+    let connection = shutdown.track();
+    Ok(h2c::upgrade(req, move |io| async move {
+        let _connection = connection;
+
+        let service = {
+            let shutdown = shutdown.clone();
+            service_fn(move |req| proxy(req, peer, gateway.clone(), shutdown.clone()))
+        };
+        let conn = h2c_builder.serve_connection(io, service);
+        tokio::pin!(conn);
+
+        let result = tokio::select! {
+            result = conn.as_mut() => result,
+            _ = shutdown.triggered() => {
+                conn.as_mut().graceful_shutdown();
+                conn.await
+            }
+        };
+
+        if let Err(err) = result {
+            log::debug!("Failed to serve the h2c connection: {:?}", err);
+        }
+    }))
+}
+
+async fn proxy(
+    mut req: Request<Incoming>,
+    peer: SocketAddr,
+    gateway: Arc<Gateway>,
+    shutdown: Shutdown,
 ) -> Result<Response<GatewayBody>, hyper::Error> {
-    Ok(proxy_bridge.determine(req).await)
+    // Tunnels outlive the connection they were upgraded from, and are tracked from the request.
+    req.extensions_mut().insert(shutdown);
+
+    // Taken per request, so requests started before a reload finish on the old config.
+    let proxy_bridge = gateway.proxy_bridge();
+    Ok(proxy_bridge.determine(req, peer).await)
 }
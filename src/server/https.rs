@@ -5,7 +5,7 @@ use std::{
 };
 
 use hyper::{body::Incoming, service::service_fn, Request, Response};
-use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::rt::TokioIo;
 use tokio::{net::TcpListener, time};
 use tokio_rustls::{
     rustls::{
@@ -15,38 +15,76 @@ use tokio_rustls::{
     TlsAcceptor,
 };
 
-use crate::proxy_service::{gateway_body::GatewayBody, proxy_bridge::ProxyBridge};
+use crate::{
+    gateway::Gateway, metrics::metrics, proxy_service::gateway_body::GatewayBody,
+    types::Http2Settings,
+};
+
+use super::{http::connection_builder, shutdown::Shutdown};
 
 pub async fn start_https_server(
     address: SocketAddr,
-    proxy_bridge: &Arc<ProxyBridge>,
+    gateway: &Arc<Gateway>,
+    shutdown: &Shutdown,
+    http2: &Http2Settings,
     key_path: &str,
     cert_path: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let builder = Arc::new(connection_builder(http2));
     let rustls_config = rustls_server_config(PathBuf::from(key_path), PathBuf::from(cert_path))?;
 
     let tcp_listener = TcpListener::bind(&address).await?;
     let tls_acceptor = TlsAcceptor::from(rustls_config);
 
     loop {
-        match tcp_listener.accept().await {
-            Ok((tcp_stream, _)) => {
+        let accepted = tokio::select! {
+            accepted = tcp_listener.accept() => accepted,
+            _ = shutdown.triggered() => {
+                log::info!("Stopped accepting connections on {}", address);
+                return Ok(());
+            }
+        };
+
+        match accepted {
+            Ok((tcp_stream, peer)) => {
+                metrics().connection_accepted("https");
+
                 let tls_acceptor = tls_acceptor.clone();
-                let proxy_bridge = proxy_bridge.clone();
+                let gateway = gateway.clone();
+
+                let builder = builder.clone();
+                let shutdown = shutdown.clone();
+                let service = {
+                    let shutdown = shutdown.clone();
+                    Arc::new(service_fn(move |req| {
+                        wrapper(req, peer, gateway.clone(), shutdown.clone())
+                    }))
+                };
+                let connection = shutdown.track();
 
-                let service = Arc::new(service_fn(move |req| wrapper(req, proxy_bridge.clone())));
                 tokio::spawn(async move {
+                    let _connection = connection;
+
                     let tls_stream = match tls_acceptor.accept(tcp_stream).await {
                         Ok(s) => s,
                         Err(e) => {
                             log::error!("Error during TLS handshake: {:?}", e);
+                            metrics().tls_handshake_failed();
                             return;
                         }
                     };
 
-                    let _ = hyper_util::server::conn::auto::Builder::new(TokioExecutor::new())
-                        .serve_connection(TokioIo::new(tls_stream), service)
-                        .await;
+                    let conn =
+                        builder.serve_connection_with_upgrades(TokioIo::new(tls_stream), service);
+                    tokio::pin!(conn);
+
+                    tokio::select! {
+                        _ = conn.as_mut() => {}
+                        _ = shutdown.triggered() => {
+                            conn.as_mut().graceful_shutdown();
+                            let _ = conn.await;
+                        }
+                    }
                 });
             }
             Err(e) => {
@@ -61,10 +99,17 @@ pub async fn start_https_server(
 }
 
 async fn wrapper(
-    req: Request<Incoming>,
-    proxy_bridge: Arc<ProxyBridge>,
+    mut req: Request<Incoming>,
+    peer: SocketAddr,
+    gateway: Arc<Gateway>,
+    shutdown: Shutdown,
 ) -> Result<Response<GatewayBody>, hyper::Error> {
-    Ok(proxy_bridge.determine(req).await)
+    // Tunnels outlive the connection they were upgraded from, and are tracked from the request.
+    req.extensions_mut().insert(shutdown);
+
+    // Taken per request, so requests started before a reload finish on the old config.
+    let proxy_bridge = gateway.proxy_bridge();
+    Ok(proxy_bridge.determine(req, peer).await)
 }
 
 fn rustls_server_config(
@@ -79,14 +124,11 @@ fn rustls_server_config(
 
     let key = PrivateKeyDer::from_pem_file(key)?;
 
-    let certs = CertificateDer::pem_file_iter(cert)?
-        .map(|cert| cert.unwrap())
-        .collect::<Vec<_>>();
+    let certs = CertificateDer::pem_file_iter(cert)?.collect::<Result<Vec<_>, _>>()?;
 
     let mut config = ServerConfig::builder()
         .with_no_client_auth()
-        .with_single_cert(certs, key)
-        .expect("Bad certificate or key");
+        .with_single_cert(certs, key)?;
 
     config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
 
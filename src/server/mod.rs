@@ -1,3 +1,6 @@
+pub mod admin;
+pub mod h2c;
 pub mod http;
 pub mod https;
 pub mod server_manager;
+pub mod shutdown;
@@ -3,36 +3,82 @@ use std::{
     sync::Arc,
 };
 
-use crate::{proxy_service::proxy_bridge::ProxyBridge, types::ServerSettings};
+use crate::{gateway::Gateway, types::ServerSettings};
 
-use super::{http::start_http_server, https::start_https_server};
+use super::{
+    admin::{start_admin_server, AdminState},
+    http::start_http_server,
+    https::start_https_server,
+    shutdown::Shutdown,
+};
 
 pub struct ServerManager {
     settings: ServerSettings,
-    proxy_bridge: Arc<ProxyBridge>,
+    gateway: Arc<Gateway>,
+    admin_state: Arc<AdminState>,
+    shutdown: Shutdown,
 }
 
 impl ServerManager {
-    pub fn new(settings: ServerSettings, proxy_bridge: Arc<ProxyBridge>) -> Self {
+    pub fn new(
+        settings: ServerSettings,
+        gateway: Arc<Gateway>,
+        admin_state: Arc<AdminState>,
+        shutdown: Shutdown,
+    ) -> Self {
         Self {
             settings,
-            proxy_bridge,
+            gateway,
+            admin_state,
+            shutdown,
         }
     }
 
+    /// Serves until `SIGTERM` or `SIGINT`, then drains the open connections.
     pub async fn start_server(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let shutdown = self.shutdown.clone();
+        let shutdown_settings = self.settings.shutdown.clone();
+        tokio::spawn(async move {
+            if let Err(e) = shutdown.on_signal(&shutdown_settings).await {
+                log::error!("Failed to listen for shutdown signals: {}", e);
+            }
+        });
+
+        match &self.settings.admin {
+            Some(admin) => tokio::select! {
+                result = self.start_proxy_server() => result?,
+                result = start_admin_server(admin.address, self.admin_state.clone()) => result?,
+            },
+            None => self.start_proxy_server().await?,
+        }
+
+        self.shutdown.drain(&self.settings.shutdown).await;
+        Ok(())
+    }
+
+    async fn start_proxy_server(&self) -> Result<(), Box<dyn std::error::Error>> {
         let address: SocketAddr = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), self.settings.port);
 
         if self.settings.enable_https {
+            let (Some(key_path), Some(cert_path)) =
+                (&self.settings.key_path, &self.settings.cert_path)
+            else {
+                return Err(
+                    "cert_path and key_path must be provided when enable_https is true".into(),
+                );
+            };
+
             start_https_server(
                 address,
-                &self.proxy_bridge,
-                self.settings.key_path.as_ref().unwrap(),
-                self.settings.cert_path.as_ref().unwrap(),
+                &self.gateway,
+                &self.shutdown,
+                &self.settings.http2,
+                key_path,
+                cert_path,
             )
             .await
         } else {
-            start_http_server(address, &self.proxy_bridge).await
+            start_http_server(address, &self.gateway, &self.shutdown, &self.settings.http2).await
         }
     }
 }
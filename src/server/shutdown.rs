@@ -0,0 +1,163 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::{
+    sync::{watch, Notify},
+    time,
+};
+
+use crate::types::ShutdownSettings;
+
+/// Coordinates the graceful shutdown of the proxy listeners and reports readiness.
+#[derive(Clone)]
+pub struct Shutdown {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    ready: AtomicBool,
+    triggered: watch::Sender<bool>,
+    connections: AtomicUsize,
+    idle: Notify,
+}
+
+/// Counts a client connection as active until it is dropped.
+pub struct ConnectionGuard {
+    inner: Arc<Inner>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        if self.inner.connections.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.inner.idle.notify_waiters();
+        }
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                ready: AtomicBool::new(true),
+                triggered: watch::channel(false).0,
+                connections: AtomicUsize::new(0),
+                idle: Notify::new(),
+            }),
+        }
+    }
+
+    /// Whether the gateway accepts traffic, served by the readiness endpoint.
+    pub fn is_ready(&self) -> bool {
+        self.inner.ready.load(Ordering::Relaxed)
+    }
+
+    /// Stops accepting connections and asks the open ones to close after their current request.
+    pub fn trigger(&self) {
+        self.inner.ready.store(false, Ordering::Relaxed);
+        self.inner.triggered.send_replace(true);
+    }
+
+    /// Completes once the shutdown has been triggered.
+    pub async fn triggered(&self) {
+        let mut triggered = self.inner.triggered.subscribe();
+        let _ = triggered.wait_for(|triggered| *triggered).await;
+    }
+
+    pub fn track(&self) -> ConnectionGuard {
+        self.inner.connections.fetch_add(1, Ordering::AcqRel);
+        ConnectionGuard {
+            inner: self.inner.clone(),
+        }
+    }
+
+    pub fn active_connections(&self) -> usize {
+        self.inner.connections.load(Ordering::Acquire)
+    }
+
+    /// Completes once every tracked connection has been closed.
+    pub async fn drained(&self) {
+        loop {
+            let idle = self.inner.idle.notified();
+            if self.active_connections() == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+
+    /// Waits for `SIGTERM` or `SIGINT`, fails readiness for `readiness_delay_ms` so load balancers
+    /// stop sending traffic, and then triggers the shutdown.
+    pub async fn on_signal(&self, settings: &ShutdownSettings) -> std::io::Result<()> {
+        let signal = wait_for_signal().await?;
+        log::info!("Received {}, shutting down", signal);
+
+        self.inner.ready.store(false, Ordering::Relaxed);
+        if settings.readiness_delay_ms > 0 {
+            log::info!(
+                "Failing readiness for {}ms before closing the listeners",
+                settings.readiness_delay_ms
+            );
+            time::sleep(Duration::from_millis(settings.readiness_delay_ms)).await;
+        }
+
+        self.trigger();
+        Ok(())
+    }
+
+    /// Waits for the open connections to finish, up to `drain_timeout_ms` or a second signal, and
+    /// returns how many were still open.
+    pub async fn drain(&self, settings: &ShutdownSettings) -> usize {
+        log::info!(
+            "Draining {} connections for up to {}ms",
+            self.active_connections(),
+            settings.drain_timeout_ms
+        );
+
+        let drain_timeout = Duration::from_millis(settings.drain_timeout_ms);
+
+        tokio::select! {
+            drained = time::timeout(drain_timeout, self.drained()) => {
+                if drained.is_ok() {
+                    log::info!("All connections closed");
+                    return 0;
+                }
+            }
+            Ok(signal) = wait_for_signal() => {
+                log::warn!("Received {} again, skipping the drain", signal);
+            }
+        }
+
+        let open = self.active_connections();
+        log::warn!("Force-closing {} connections", open);
+        open
+    }
+}
+
+async fn wait_for_signal() -> std::io::Result<&'static str> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            _ = terminate.recv() => Ok("SIGTERM"),
+            result = tokio::signal::ctrl_c() => result.map(|_| "SIGINT"),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await.map(|_| "Ctrl-C")
+    }
+}
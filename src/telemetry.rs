@@ -0,0 +1,481 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use http_body_util::Full;
+use hyper::{
+    body::Bytes,
+    header::{HeaderName, HeaderValue, CONTENT_TYPE},
+    HeaderMap, Method, Request,
+};
+use serde_json::{json, Value};
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::{interval, timeout, MissedTickBehavior},
+};
+
+use crate::{
+    proxy_service::{
+        gateway_body::GatewayBody,
+        proxy_handler::{build_client, HttpClient},
+    },
+    types::{Timeouts, TracingSettings, UpstreamProtocol},
+};
+
+pub const TRACEPARENT: HeaderName = HeaderName::from_static("traceparent");
+pub const TRACESTATE: HeaderName = HeaderName::from_static("tracestate");
+
+/// Spans waiting for export are dropped once the queue is full.
+const QUEUE_SIZE: usize = 4096;
+
+/// How long the collector may take to answer an export before its spans are dropped.
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Identifies a span across services, as carried by the W3C `traceparent` header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpanContext {
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+    pub sampled: bool,
+    pub trace_state: Option<String>,
+}
+
+impl SpanContext {
+    /// Parses `traceparent` and `tracestate`. Invalid values are ignored, as the spec requires.
+    pub fn extract(headers: &HeaderMap) -> Option<Self> {
+        let traceparent = headers.get(TRACEPARENT)?.to_str().ok()?.trim();
+        let mut fields = traceparent.split('-');
+
+        let version = fields.next()?;
+        let trace_id = fields.next()?;
+        let span_id = fields.next()?;
+        let flags = fields.next()?;
+
+        if version.len() != 2 || version == "ff" || (version == "00" && fields.next().is_some()) {
+            return None;
+        }
+
+        let trace_id = decode_hex::<16>(trace_id)?;
+        let span_id = decode_hex::<8>(span_id)?;
+        let flags = decode_hex::<1>(flags)?[0];
+
+        if trace_id == [0; 16] || span_id == [0; 8] {
+            return None;
+        }
+
+        let trace_state = headers
+            .get(TRACESTATE)
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| !value.is_empty())
+            .map(str::to_string);
+
+        Some(Self {
+            trace_id,
+            span_id,
+            sampled: flags & 1 == 1,
+            trace_state,
+        })
+    }
+
+    /// Sets `traceparent` and `tracestate` for the next hop.
+    pub fn inject(&self, headers: &mut HeaderMap) {
+        if let Ok(value) = HeaderValue::from_str(&self.traceparent()) {
+            headers.insert(TRACEPARENT, value);
+        }
+
+        match self
+            .trace_state
+            .as_deref()
+            .and_then(|state| HeaderValue::from_str(state).ok())
+        {
+            Some(value) => {
+                headers.insert(TRACESTATE, value);
+            }
+            None => {
+                headers.remove(TRACESTATE);
+            }
+        }
+    }
+
+    pub fn traceparent(&self) -> String {
+        format!(
+            "00-{}-{}-{:02x}",
+            encode_hex(&self.trace_id),
+            encode_hex(&self.span_id),
+            self.sampled as u8
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpanKind {
+    Server,
+    Client,
+}
+
+pub enum AttributeValue {
+    String(String),
+    Int(i64),
+}
+
+impl From<&str> for AttributeValue {
+    fn from(value: &str) -> Self {
+        AttributeValue::String(value.to_string())
+    }
+}
+
+impl From<String> for AttributeValue {
+    fn from(value: String) -> Self {
+        AttributeValue::String(value)
+    }
+}
+
+impl From<i64> for AttributeValue {
+    fn from(value: i64) -> Self {
+        AttributeValue::Int(value)
+    }
+}
+
+impl From<u16> for AttributeValue {
+    fn from(value: u16) -> Self {
+        AttributeValue::Int(value as i64)
+    }
+}
+
+struct SpanData {
+    context: SpanContext,
+    parent_span_id: Option<[u8; 8]>,
+    name: String,
+    kind: SpanKind,
+    start: SystemTime,
+    end: SystemTime,
+    attributes: Vec<(&'static str, AttributeValue)>,
+    error: Option<String>,
+}
+
+/// A span that is exported when it is dropped, if it was sampled.
+pub struct Span {
+    tracer: Arc<Tracer>,
+    data: Option<SpanData>,
+}
+
+impl Span {
+    pub fn context(&self) -> Option<&SpanContext> {
+        self.data.as_ref().map(|data| &data.context)
+    }
+
+    /// A handle to start child spans from, e.g. in another task.
+    pub fn parent(&self) -> Option<ParentSpan> {
+        Some(ParentSpan {
+            tracer: self.tracer.clone(),
+            context: self.context()?.clone(),
+        })
+    }
+
+    pub fn set_attribute(&mut self, key: &'static str, value: impl Into<AttributeValue>) {
+        if let Some(data) = &mut self.data {
+            data.attributes.push((key, value.into()));
+        }
+    }
+
+    pub fn set_error(&mut self, message: impl Into<String>) {
+        if let Some(data) = &mut self.data {
+            data.error = Some(message.into());
+        }
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some(mut data) = self.data.take() {
+            if data.context.sampled {
+                data.end = SystemTime::now();
+                self.tracer.export(data);
+            }
+        }
+    }
+}
+
+/// The context of a span together with the tracer that started it.
+#[derive(Clone)]
+pub struct ParentSpan {
+    tracer: Arc<Tracer>,
+    context: SpanContext,
+}
+
+impl ParentSpan {
+    pub fn context(&self) -> &SpanContext {
+        &self.context
+    }
+
+    pub fn child(&self, name: impl Into<String>, kind: SpanKind) -> Span {
+        self.tracer.start_span(name, kind, Some(&self.context))
+    }
+}
+
+/// Starts spans and exports the sampled ones with OTLP/HTTP, in batches.
+pub struct Tracer {
+    sampling_ratio: f64,
+    queue: mpsc::Sender<Command>,
+}
+
+enum Command {
+    Export(SpanData),
+    Flush(oneshot::Sender<()>),
+}
+
+impl Tracer {
+    /// Creates the tracer and spawns its exporter.
+    pub fn new(settings: &TracingSettings) -> std::io::Result<Arc<Self>> {
+        let (queue, spans) = mpsc::channel(QUEUE_SIZE);
+
+        let exporter = Exporter {
+            client: build_client(&Timeouts::default(), UpstreamProtocol::Http1)?,
+            endpoint: format!("{}/v1/traces", settings.endpoint.trim_end_matches('/')),
+            service_name: settings.service_name.clone(),
+            batch_size: settings.batch_size.max(1),
+            interval: Duration::from_millis(settings.export_interval_ms.max(1)),
+        };
+        tokio::spawn(exporter.run(spans));
+
+        Ok(Arc::new(Self {
+            sampling_ratio: settings.sampling_ratio.clamp(0.0, 1.0),
+            queue,
+        }))
+    }
+
+    /// Starts a span. A root span is sampled with the configured ratio, a child span follows the
+    /// decision of its parent.
+    pub fn start_span(
+        self: &Arc<Self>,
+        name: impl Into<String>,
+        kind: SpanKind,
+        parent: Option<&SpanContext>,
+    ) -> Span {
+        let trace_id = match parent {
+            Some(parent) => parent.trace_id,
+            None => random_id::<16>(),
+        };
+
+        let sampled = match parent {
+            Some(parent) => parent.sampled,
+            None => self.should_sample(&trace_id),
+        };
+
+        Span {
+            tracer: self.clone(),
+            data: Some(SpanData {
+                context: SpanContext {
+                    trace_id,
+                    span_id: random_id::<8>(),
+                    sampled,
+                    trace_state: parent.and_then(|parent| parent.trace_state.clone()),
+                },
+                parent_span_id: parent.map(|parent| parent.span_id),
+                name: name.into(),
+                kind,
+                start: SystemTime::now(),
+                end: SystemTime::now(),
+                attributes: Vec::new(),
+                error: None,
+            }),
+        }
+    }
+
+    /// Derives the decision from the trace id, so every service using the same ratio agrees.
+    fn should_sample(&self, trace_id: &[u8; 16]) -> bool {
+        let mut low = [0; 8];
+        low.copy_from_slice(&trace_id[8..]);
+        let value = u64::from_be_bytes(low) >> 11;
+
+        (value as f64) < self.sampling_ratio * (1u64 << 53) as f64
+    }
+
+    /// Waits until the spans ended so far are exported.
+    pub async fn flush(&self) {
+        let (done, wait) = oneshot::channel();
+        if self.queue.send(Command::Flush(done)).await.is_ok() {
+            let _ = wait.await;
+        }
+    }
+
+    fn export(&self, span: SpanData) {
+        if self.queue.try_send(Command::Export(span)).is_err() {
+            log::debug!("Span export queue is full, dropping span");
+        }
+    }
+}
+
+struct Exporter {
+    client: HttpClient,
+    endpoint: String,
+    service_name: String,
+    batch_size: usize,
+    interval: Duration,
+}
+
+impl Exporter {
+    async fn run(self, mut commands: mpsc::Receiver<Command>) {
+        let mut batch = Vec::new();
+        let mut ticker = interval(self.interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                command = commands.recv() => match command {
+                    Some(Command::Export(span)) => {
+                        batch.push(span);
+                        if batch.len() >= self.batch_size {
+                            self.send(std::mem::take(&mut batch)).await;
+                        }
+                    }
+                    Some(Command::Flush(done)) => {
+                        self.send(std::mem::take(&mut batch)).await;
+                        let _ = done.send(());
+                    }
+                    None => {
+                        self.send(batch).await;
+                        return;
+                    }
+                },
+                _ = ticker.tick() => {
+                    if !batch.is_empty() {
+                        self.send(std::mem::take(&mut batch)).await;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn send(&self, batch: Vec<SpanData>) {
+        if batch.is_empty() {
+            return;
+        }
+
+        let body = otlp_json(&self.service_name, &batch).to_string();
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(&self.endpoint)
+            .header(CONTENT_TYPE, "application/json")
+            .body(GatewayBody::Buffered(Full::new(Bytes::from(body))));
+
+        let req = match req {
+            Ok(req) => req,
+            Err(e) => {
+                log::error!("Invalid trace endpoint {:?}: {}", self.endpoint, e);
+                return;
+            }
+        };
+
+        match timeout(EXPORT_TIMEOUT, self.client.request(req)).await {
+            Ok(Ok(res)) if res.status().is_success() => {
+                log::debug!("Exported {} spans", batch.len());
+            }
+            Ok(Ok(res)) => log::warn!("Trace collector responded with {}", res.status()),
+            Ok(Err(e)) => log::warn!("Failed to export spans: {}", e),
+            Err(_) => log::warn!(
+                "Trace collector did not answer within {}s, dropping {} spans",
+                EXPORT_TIMEOUT.as_secs(),
+                batch.len()
+            ),
+        }
+    }
+}
+
+/// Encodes spans as an OTLP `ExportTraceServiceRequest` in the JSON encoding.
+fn otlp_json(service_name: &str, batch: &[SpanData]) -> Value {
+    let spans = batch
+        .iter()
+        .map(|span| {
+            let mut value = json!({
+                "traceId": encode_hex(&span.context.trace_id),
+                "spanId": encode_hex(&span.context.span_id),
+                "name": span.name,
+                "kind": match span.kind {
+                    SpanKind::Server => 2,
+                    SpanKind::Client => 3,
+                },
+                "startTimeUnixNano": unix_nanos(span.start).to_string(),
+                "endTimeUnixNano": unix_nanos(span.end).to_string(),
+                "attributes": span
+                    .attributes
+                    .iter()
+                    .map(|(key, value)| attribute(key, value))
+                    .collect::<Vec<_>>(),
+                "status": match &span.error {
+                    Some(message) => json!({ "code": 2, "message": message }),
+                    None => json!({ "code": 0 }),
+                },
+            });
+
+            if let Some(parent_span_id) = &span.parent_span_id {
+                value["parentSpanId"] = json!(encode_hex(parent_span_id));
+            }
+            if let Some(trace_state) = &span.context.trace_state {
+                value["traceState"] = json!(trace_state);
+            }
+
+            value
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [attribute("service.name", &AttributeValue::from(service_name))],
+            },
+            "scopeSpans": [{
+                "scope": { "name": "oxidegate", "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+fn attribute(key: &str, value: &AttributeValue) -> Value {
+    let value = match value {
+        AttributeValue::String(value) => json!({ "stringValue": value }),
+        AttributeValue::Int(value) => json!({ "intValue": value.to_string() }),
+    };
+
+    json!({ "key": key, "value": value })
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
+
+fn random_id<const N: usize>() -> [u8; N] {
+    loop {
+        let mut id = [0; N];
+        id.iter_mut().for_each(|byte| *byte = fastrand::u8(..));
+        if id != [0; N] {
+            return id;
+        }
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex<const N: usize>(value: &str) -> Option<[u8; N]> {
+    if value.len() != N * 2
+        || !value
+            .bytes()
+            .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    {
+        return None;
+    }
+
+    let mut bytes = [0; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&value[i * 2..i * 2 + 2], 16).ok()?;
+    }
+
+    Some(bytes)
+}
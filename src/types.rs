@@ -1,6 +1,8 @@
-use serde::Deserialize;
+use std::{net::SocketAddr, time::Duration};
 
-#[derive(Debug, Deserialize, Clone)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ServerSettings {
     #[serde(default)]
     pub enable_https: bool,
@@ -8,30 +10,369 @@ pub struct ServerSettings {
     pub port: u16,
     pub cert_path: Option<String>,
     pub key_path: Option<String>,
+    #[serde(default)]
+    pub error_format: ErrorFormat,
+    #[serde(default)]
+    pub forwarded_headers: ForwardedHeaders,
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+    #[serde(default)]
+    pub request_id: RequestIdFormat,
+    pub access_log: Option<AccessLog>,
+    pub admin: Option<AdminSettings>,
+    pub tracing: Option<TracingSettings>,
+    /// How often the config file is checked for changes, `0` disables watching.
+    #[serde(default = "default_config_watch_interval_ms")]
+    pub config_watch_interval_ms: u64,
+    #[serde(default)]
+    pub shutdown: ShutdownSettings,
+    #[serde(default)]
+    pub http2: Http2Settings,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum ErrorFormat {
+    #[default]
+    Empty,
+    Json,
+    Html,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum ForwardedHeaders {
+    #[default]
+    XForwarded,
+    Forwarded,
+    Both,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum RequestIdFormat {
+    #[default]
+    Uuid,
+    Ulid,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AdminSettings {
+    #[serde(default = "default_admin_address")]
+    pub address: SocketAddr,
+}
+
+/// HTTP/2 settings of the client listeners. Unset values keep hyper's defaults.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Http2Settings {
+    pub max_concurrent_streams: Option<u32>,
+    pub initial_stream_window_size: Option<u32>,
+    pub initial_connection_window_size: Option<u32>,
+    /// Sizes the windows from the measured bandwidth-delay product, ignoring the window sizes.
+    #[serde(default)]
+    pub adaptive_window: bool,
+    pub max_frame_size: Option<u32>,
+    /// Interval of the PING frames sent to detect dead connections, disabled when not set.
+    pub keep_alive_interval_ms: Option<u64>,
+    /// How long to wait for a PING acknowledgement before closing the connection.
+    pub keep_alive_timeout_ms: Option<u64>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShutdownSettings {
+    /// How long the readiness endpoint fails before the listeners are closed.
+    #[serde(default)]
+    pub readiness_delay_ms: u64,
+    /// How long open connections may take to finish before they are closed.
+    #[serde(default = "default_drain_timeout_ms")]
+    pub drain_timeout_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TracingSettings {
+    /// Base URL of the OTLP/HTTP collector, spans are posted to `<endpoint>/v1/traces`.
+    #[serde(default = "default_tracing_endpoint")]
+    pub endpoint: String,
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+    /// Share of new traces that are recorded, from `0.0` to `1.0`.
+    #[serde(default = "default_sampling_ratio")]
+    pub sampling_ratio: f64,
+    #[serde(default = "default_tracing_batch_size")]
+    pub batch_size: usize,
+    #[serde(default = "default_tracing_export_interval_ms")]
+    pub export_interval_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AccessLog {
+    #[serde(default)]
+    pub format: AccessLogFormat,
+    /// Template of the `Custom` format.
+    pub template: Option<String>,
+    /// File the log is appended to, stdout when not set.
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum AccessLogFormat {
+    Common,
+    #[default]
+    Combined,
+    Json,
+    Custom,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Frontend {
-    #[serde(rename = "path_prefixes")]
+    pub name: Option<String>,
+    #[serde(rename = "path_prefixes", default)]
     pub path_prefix: Vec<String>,
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    #[serde(default)]
+    pub grpc_services: Vec<String>,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(rename = "match", default)]
+    pub predicates: RoutePredicates,
     pub backend: String,
+    #[serde(default)]
+    pub timeouts: Timeouts,
+    pub strip_prefix: Option<String>,
+    pub add_prefix: Option<String>,
+    #[serde(default)]
+    pub rewrite: Vec<RewriteRule>,
+    #[serde(default)]
+    pub request_headers: HeaderRules,
+    #[serde(default)]
+    pub response_headers: HeaderRules,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RewriteRule {
+    pub pattern: String,
+    pub replacement: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RoutePredicates {
+    #[serde(default)]
+    pub methods: Vec<String>,
+    #[serde(default)]
+    pub headers: Vec<ValueMatch>,
+    #[serde(default)]
+    pub query: Vec<ValueMatch>,
+    #[serde(default)]
+    pub cookies: Vec<ValueMatch>,
+}
+
+impl RoutePredicates {
+    pub fn len(&self) -> usize {
+        self.methods.len().min(1) + self.headers.len() + self.query.len() + self.cookies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Matches a named value, or only its presence when `value` is not set.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ValueMatch {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct HeaderRules {
+    #[serde(default)]
+    pub add: Vec<HeaderRule>,
+    #[serde(default)]
+    pub set: Vec<HeaderRule>,
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+impl HeaderRules {
+    pub fn is_empty(&self) -> bool {
+        self.add.is_empty() && self.set.is_empty() && self.remove.is_empty()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HeaderRule {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct BackendServer {
     pub server: String,
     pub weight: Option<u32>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Backend {
     pub name: String,
     pub servers: Vec<BackendServer>,
     #[serde(default = "default_lb_algorithm")]
     pub lb_algorithm: LbAlgorithm,
+    pub health_check: Option<HealthCheck>,
+    pub outlier_detection: Option<OutlierDetection>,
+    pub retry: Option<Retry>,
+    #[serde(default)]
+    pub timeouts: Timeouts,
+    #[serde(default)]
+    pub preserve_host: bool,
+    #[serde(default)]
+    pub protocol: UpstreamProtocol,
+    #[serde(default)]
+    pub request_headers: HeaderRules,
+    #[serde(default)]
+    pub response_headers: HeaderRules,
+}
+
+/// Protocol spoken to the servers of a backend.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum UpstreamProtocol {
+    #[default]
+    Http1,
+    /// HTTP/2 over TLS, negotiated with ALPN.
+    Http2,
+    /// HTTP/2 over cleartext with prior knowledge.
+    H2c,
+    /// HTTP/2 when the server selects it with ALPN, HTTP/1.1 otherwise.
+    Auto,
 }
 
-#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Timeouts {
+    pub connect_timeout_ms: Option<u64>,
+    pub request_timeout_ms: Option<u64>,
+    pub idle_timeout_ms: Option<u64>,
+    pub pool_idle_timeout_ms: Option<u64>,
+    pub tunnel_idle_timeout_ms: Option<u64>,
+}
+
+impl Timeouts {
+    /// Fills every unset timeout from `fallback`.
+    pub fn or(&self, fallback: &Timeouts) -> Timeouts {
+        Timeouts {
+            connect_timeout_ms: self.connect_timeout_ms.or(fallback.connect_timeout_ms),
+            request_timeout_ms: self.request_timeout_ms.or(fallback.request_timeout_ms),
+            idle_timeout_ms: self.idle_timeout_ms.or(fallback.idle_timeout_ms),
+            pool_idle_timeout_ms: self.pool_idle_timeout_ms.or(fallback.pool_idle_timeout_ms),
+            tunnel_idle_timeout_ms: self
+                .tunnel_idle_timeout_ms
+                .or(fallback.tunnel_idle_timeout_ms),
+        }
+    }
+
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        self.connect_timeout_ms.map(Duration::from_millis)
+    }
+
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_millis(
+            self.request_timeout_ms
+                .unwrap_or(default_request_timeout_ms()),
+        )
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout_ms.map(Duration::from_millis)
+    }
+
+    pub fn pool_idle_timeout(&self) -> Duration {
+        Duration::from_millis(
+            self.pool_idle_timeout_ms
+                .unwrap_or(default_pool_idle_timeout_ms()),
+        )
+    }
+
+    pub fn tunnel_idle_timeout(&self) -> Duration {
+        Duration::from_millis(
+            self.tunnel_idle_timeout_ms
+                .unwrap_or(default_tunnel_idle_timeout_ms()),
+        )
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HealthCheck {
+    #[serde(default = "default_health_check_path")]
+    pub path: String,
+    #[serde(default = "default_health_check_interval_ms")]
+    pub interval_ms: u64,
+    #[serde(default = "default_health_check_timeout_ms")]
+    pub timeout_ms: u64,
+    #[serde(default)]
+    pub expected_status: StatusRange,
+    #[serde(default = "default_healthy_threshold")]
+    pub healthy_threshold: u32,
+    #[serde(default = "default_unhealthy_threshold")]
+    pub unhealthy_threshold: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OutlierDetection {
+    #[serde(default = "default_consecutive_failures")]
+    pub consecutive_failures: u32,
+    pub failure_rate_threshold: Option<f64>,
+    #[serde(default = "default_outlier_window_ms")]
+    pub window_ms: u64,
+    #[serde(default = "default_outlier_min_requests")]
+    pub min_requests: u32,
+    #[serde(default = "default_base_ejection_ms")]
+    pub base_ejection_ms: u64,
+    #[serde(default = "default_max_ejection_ms")]
+    pub max_ejection_ms: u64,
+    #[serde(default = "default_max_ejection_percent")]
+    pub max_ejection_percent: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Retry {
+    #[serde(default = "default_retry_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_retry_on")]
+    pub retry_on: Vec<RetryOn>,
+    #[serde(default)]
+    pub status_codes: Vec<u16>,
+    #[serde(default = "default_true")]
+    pub idempotent_only: bool,
+    pub per_try_timeout_ms: Option<u64>,
+    #[serde(default = "default_retry_backoff_base_ms")]
+    pub backoff_base_ms: u64,
+    #[serde(default = "default_retry_backoff_max_ms")]
+    pub backoff_max_ms: u64,
+    #[serde(default = "default_retry_max_body_bytes")]
+    pub max_body_bytes: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum RetryOn {
+    ConnectFailure,
+    Timeout,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct StatusRange {
+    pub min: u16,
+    pub max: u16,
+}
+
+impl StatusRange {
+    pub fn contains(&self, status: u16) -> bool {
+        self.min <= status && status <= self.max
+    }
+}
+
+impl Default for StatusRange {
+    fn default() -> Self {
+        StatusRange { min: 200, max: 399 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
 pub enum LbAlgorithm {
     RoundRobin,
     LeastConnections,
@@ -41,9 +382,93 @@ pub enum LbAlgorithm {
 fn default_lb_algorithm() -> LbAlgorithm {
     LbAlgorithm::RoundRobin
 }
+fn default_admin_address() -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], 9090))
+}
+fn default_tracing_endpoint() -> String {
+    "http://localhost:4318".to_string()
+}
+fn default_service_name() -> String {
+    "oxidegate".to_string()
+}
+fn default_sampling_ratio() -> f64 {
+    1.0
+}
+fn default_tracing_batch_size() -> usize {
+    512
+}
+fn default_tracing_export_interval_ms() -> u64 {
+    5000
+}
+fn default_config_watch_interval_ms() -> u64 {
+    2_000
+}
+fn default_drain_timeout_ms() -> u64 {
+    30_000
+}
 fn default_port() -> u16 {
     3000
 }
+fn default_health_check_path() -> String {
+    "/".to_string()
+}
+fn default_health_check_interval_ms() -> u64 {
+    10_000
+}
+fn default_health_check_timeout_ms() -> u64 {
+    2_000
+}
+fn default_healthy_threshold() -> u32 {
+    2
+}
+fn default_unhealthy_threshold() -> u32 {
+    3
+}
+fn default_consecutive_failures() -> u32 {
+    5
+}
+fn default_outlier_window_ms() -> u64 {
+    30_000
+}
+fn default_outlier_min_requests() -> u32 {
+    10
+}
+fn default_base_ejection_ms() -> u64 {
+    30_000
+}
+fn default_max_ejection_ms() -> u64 {
+    300_000
+}
+fn default_max_ejection_percent() -> u32 {
+    50
+}
+fn default_retry_max_attempts() -> u32 {
+    3
+}
+fn default_retry_on() -> Vec<RetryOn> {
+    vec![RetryOn::ConnectFailure, RetryOn::Timeout]
+}
+fn default_retry_backoff_base_ms() -> u64 {
+    25
+}
+fn default_retry_backoff_max_ms() -> u64 {
+    250
+}
+fn default_retry_max_body_bytes() -> usize {
+    64 * 1024
+}
+fn default_request_timeout_ms() -> u64 {
+    5_000
+}
+fn default_pool_idle_timeout_ms() -> u64 {
+    90_000
+}
+fn default_tunnel_idle_timeout_ms() -> u64 {
+    300_000
+}
+fn default_true() -> bool {
+    true
+}
 
 impl Default for ServerSettings {
     fn default() -> Self {
@@ -52,6 +477,25 @@ impl Default for ServerSettings {
             port: default_port(),
             cert_path: None,
             key_path: None,
+            error_format: ErrorFormat::default(),
+            forwarded_headers: ForwardedHeaders::default(),
+            trusted_proxies: Vec::new(),
+            request_id: RequestIdFormat::default(),
+            access_log: None,
+            admin: None,
+            tracing: None,
+            config_watch_interval_ms: default_config_watch_interval_ms(),
+            shutdown: ShutdownSettings::default(),
+            http2: Http2Settings::default(),
+        }
+    }
+}
+
+impl Default for ShutdownSettings {
+    fn default() -> Self {
+        ShutdownSettings {
+            readiness_delay_ms: 0,
+            drain_timeout_ms: default_drain_timeout_ms(),
         }
     }
 }
@@ -0,0 +1,93 @@
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use hyper::{Request, StatusCode};
+    use oxidegate::{
+        proxy_service::{
+            access_log::{AccessEntry, AccessLogger},
+            context::RequestStats,
+            gateway_body::ByteCounter,
+        },
+        types::{AccessLog, AccessLogFormat},
+    };
+
+    fn write_entry(format: AccessLogFormat, template: Option<&str>) -> String {
+        let path = std::env::temp_dir().join(format!("oxidegate-access-{}.log", fastrand::u64(..)));
+        let config = AccessLog {
+            format,
+            template: template.map(str::to_string),
+            path: Some(path.to_string_lossy().to_string()),
+        };
+        let logger = Arc::new(AccessLogger::new(&config).unwrap());
+
+        let req = Request::post("/api/users?page=2")
+            .header("host", "api.example.com")
+            .header("user-agent", "curl/8.0")
+            .body(())
+            .unwrap();
+
+        let stats = Arc::new(RequestStats::default());
+        stats.set_route(Some("api".to_string()), "api-backend");
+        stats.record_upstream("http://10.0.0.1:8080", Duration::from_millis(12));
+        stats.bytes_in.add(42);
+
+        let mut entry = AccessEntry::start(
+            logger.clone(),
+            &req,
+            "192.0.2.7".parse().unwrap(),
+            "abc".to_string(),
+            stats,
+        );
+        entry.set_status(StatusCode::CREATED);
+        entry.add(1024);
+        drop(entry);
+        logger.flush();
+
+        let line = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        line
+    }
+
+    #[test]
+    fn test_combined_format() {
+        let line = write_entry(AccessLogFormat::Combined, None);
+
+        assert!(line.starts_with("192.0.2.7 - - ["));
+        assert!(line.contains(
+            "] \"POST /api/users?page=2 HTTP/1.1\" 201 1024 \"-\" \"curl/8.0\" request_id=abc"
+        ));
+        assert!(line.contains("frontend=\"api\" server=\"http://10.0.0.1:8080\" bytes_in=42"));
+        assert!(line.ends_with('\n'));
+    }
+
+    #[test]
+    fn test_json_format() {
+        let line = write_entry(AccessLogFormat::Json, None);
+        let entry: serde_json::Value = serde_json::from_str(&line).unwrap();
+
+        assert_eq!(entry["status"], 201);
+        assert_eq!(entry["bytes_in"], 42);
+        assert_eq!(entry["bytes_out"], 1024);
+        assert_eq!(entry["frontend"], "api");
+        assert_eq!(entry["server"], "http://10.0.0.1:8080");
+        assert_eq!(entry["host"], "api.example.com");
+        assert_eq!(entry["upstream_latency_ms"], 12.0);
+    }
+
+    #[test]
+    fn test_custom_format() {
+        let line = write_entry(
+            AccessLogFormat::Custom,
+            Some("${method} ${uri} ${status} ${server} ${referer}"),
+        );
+        assert_eq!(line, "POST /api/users?page=2 201 http://10.0.0.1:8080 -\n");
+
+        let config = AccessLog {
+            format: AccessLogFormat::Custom,
+            template: Some("${unknown}".to_string()),
+            path: None,
+        };
+        assert!(AccessLogger::new(&config).is_err());
+    }
+}
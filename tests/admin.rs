@@ -0,0 +1,254 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+    use http_body_util::{BodyExt, Full};
+    use hyper::{body::Bytes, Method, Request, StatusCode};
+    use oxidegate::{
+        config::Config,
+        gateway::Gateway,
+        proxy_service::{gateway_body::GatewayBody, proxy_handler::build_client},
+        server::{
+            admin::{serve_admin, AdminState},
+            shutdown::Shutdown,
+        },
+        types::{Timeouts, UpstreamProtocol},
+    };
+    use serde_json::{json, Value};
+    use tokio::net::TcpListener;
+
+    use crate::common::{closed_port, stub};
+
+    /// Serves the admin endpoints of a gateway running `config`.
+    async fn admin(config: &str) -> SocketAddr {
+        let config: Config = serde_yaml::from_str(config).unwrap();
+        let gateway = Arc::new(Gateway::new(config, None, None).unwrap());
+        let state = Arc::new(AdminState::new(gateway, Shutdown::new()));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _ = serve_admin(listener, state).await;
+        });
+
+        addr
+    }
+
+    async fn request(
+        admin: SocketAddr,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let client = build_client(&Timeouts::default(), UpstreamProtocol::Http1).unwrap();
+        let body = match body {
+            Some(body) => GatewayBody::Buffered(Full::new(Bytes::from(body.to_string()))),
+            None => GatewayBody::Empty,
+        };
+        let req = Request::builder()
+            .method(method)
+            .uri(format!("http://{}{}", admin, path))
+            .body(body)
+            .unwrap();
+
+        let res = client.request(req).await.unwrap();
+        let status = res.status();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    async fn get(admin: SocketAddr, path: &str) -> Value {
+        let (status, body) = request(admin, Method::GET, path, None).await;
+        assert_eq!(status, StatusCode::OK);
+        body
+    }
+
+    #[tokio::test]
+    async fn test_admin_endpoints() {
+        let live = stub("ok", Duration::ZERO).await;
+        let dead = closed_port().await;
+        let admin = admin(&format!(
+            r#"
+            frontends:
+              - {{name: api, path_prefixes: ["/api/*"], backend: api, timeouts: {{request_timeout_ms: 500}}}}
+            backends:
+              - name: api
+                lb_algorithm: LeastConnections
+                servers: [{{server: "http://{}"}}, {{server: "http://{}"}}]
+                health_check: {{interval_ms: 20, unhealthy_threshold: 1}}
+                timeouts: {{connect_timeout_ms: 100}}
+            "#,
+            live, dead
+        ))
+        .await;
+
+        let config = get(admin, "/config").await;
+        assert_eq!(config["frontends"][0]["backend"], "api");
+        assert_eq!(config["backends"][0]["name"], "api");
+        assert!(config["server"].is_object());
+
+        let routes = get(admin, "/routes").await;
+        assert_eq!(routes[0]["index"], 0);
+        assert_eq!(routes[0]["name"], "api");
+        assert_eq!(routes[0]["path_prefixes"][0], "/api/*");
+        assert_eq!(routes[0]["backend"], "api");
+        assert_eq!(routes[0]["timeouts"]["request_timeout_ms"], 500);
+        assert_eq!(routes[0]["timeouts"]["connect_timeout_ms"], 100);
+
+        let version = get(admin, "/version").await;
+        assert_eq!(version["name"], "oxidegate");
+        assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+        assert!(version["started_at"].as_u64().unwrap() > 0);
+        assert!(version["uptime_seconds"].is_u64());
+
+        // The dead server fails its first health check.
+        let mut backends = get(admin, "/backends").await;
+        for _ in 0..100 {
+            if backends[0]["servers"][1]["healthy"] == false {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            backends = get(admin, "/backends").await;
+        }
+
+        let backend = &backends[0];
+        assert_eq!(backend["name"], "api");
+        assert_eq!(backend["lb_algorithm"], "LeastConnections");
+        let servers = backend["servers"].as_array().unwrap();
+        assert_eq!(servers.len(), 2);
+        for (server, addr, healthy) in [(&servers[0], live, true), (&servers[1], dead, false)] {
+            assert_eq!(server["server"], format!("http://{}", addr));
+            assert_eq!(server["weight"], 1);
+            assert_eq!(server["healthy"], healthy);
+            assert_eq!(server["ejected"], false);
+            assert_eq!(server["draining"], false);
+            assert_eq!(server["active_connections"], 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_update_servers() {
+        let admin = admin(
+            r#"
+            frontends:
+              - {backend: api}
+              - {backend: weighted}
+            backends:
+              - {name: api, servers: [{server: "http://10.0.0.1"}]}
+              - {name: weighted, lb_algorithm: WeightedRoundRobin, servers: [{server: "http://10.0.0.1"}]}
+            "#,
+        )
+        .await;
+        let servers = "/backends/api/servers";
+        let change = |server: &str, mut change: Value| {
+            change["server"] = json!(server);
+            Some(change)
+        };
+
+        let (status, _) = request(
+            admin,
+            Method::POST,
+            "/backends/missing/servers",
+            change("http://10.0.0.2", json!({})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, _) = request(
+            admin,
+            Method::PATCH,
+            servers,
+            change("http://10.0.0.9", json!({"drain": true})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, _) = request(
+            admin,
+            Method::POST,
+            servers,
+            change("http://10.0.0.1", json!({})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let (status, _) = request(
+            admin,
+            Method::DELETE,
+            servers,
+            change("http://10.0.0.1", json!({})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let (status, _) =
+            request(admin, Method::POST, servers, change("10.0.0.2", json!({}))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = request(
+            admin,
+            Method::PATCH,
+            servers,
+            change("http://10.0.0.1", json!({})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, body) = request(
+            admin,
+            Method::PATCH,
+            servers,
+            change("http://10.0.0.1", json!({"weight": 3})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"]
+            .as_str()
+            .unwrap()
+            .contains("WeightedRoundRobin"));
+
+        let (status, body) = request(
+            admin,
+            Method::PATCH,
+            "/backends/weighted/servers",
+            change("http://10.0.0.1", json!({"weight": 3})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["servers"][0]["weight"], 3);
+
+        let (status, body) = request(
+            admin,
+            Method::POST,
+            servers,
+            change("http://10.0.0.2", json!({})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["servers"].as_array().unwrap().len(), 2);
+
+        let (status, body) = request(
+            admin,
+            Method::PATCH,
+            servers,
+            change("http://10.0.0.2", json!({"drain": true})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["servers"][0]["draining"], false);
+        assert_eq!(body["servers"][1]["draining"], true);
+
+        let (status, body) = request(
+            admin,
+            Method::DELETE,
+            servers,
+            change("http://10.0.0.1", json!({})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["servers"][0]["server"], "http://10.0.0.2");
+        assert_eq!(body["servers"].as_array().unwrap().len(), 1);
+    }
+}
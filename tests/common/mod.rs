@@ -0,0 +1,113 @@
+//! Upstreams shared by the integration tests.
+#![allow(dead_code)]
+
+use std::{convert::Infallible, error::Error, future::Future, net::SocketAddr, time::Duration};
+
+use http_body_util::{Empty, Full};
+use hyper::{
+    body::{Body, Bytes, Incoming},
+    header::{CONNECTION, UPGRADE},
+    server::conn::{http1, http2},
+    service::service_fn,
+    Request, Response, StatusCode,
+};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use tokio::net::TcpListener;
+
+/// Serves `handler` over HTTP/1.1, with upgrades, on a local port.
+pub async fn serve_stub<F, Fut, B>(handler: F) -> SocketAddr
+where
+    F: Fn(Request<Incoming>) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = Response<B>> + Send + 'static,
+    B: Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |req| {
+                    let res = handler(req);
+                    async move { Ok::<_, Infallible>(res.await) }
+                });
+                let _ = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .with_upgrades()
+                    .await;
+            });
+        }
+    });
+
+    addr
+}
+
+/// Serves `handler` over HTTP/2 with prior knowledge (h2c) on a local port.
+pub async fn serve_h2c_stub<F, Fut, B>(handler: F) -> SocketAddr
+where
+    F: Fn(Request<Incoming>) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = Response<B>> + Send + 'static,
+    B: Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |req| {
+                    let res = handler(req);
+                    async move { Ok::<_, Infallible>(res.await) }
+                });
+                let _ = http2::Builder::new(TokioExecutor::new())
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    });
+
+    addr
+}
+
+/// A backend answering `body` after `delay`.
+pub async fn stub(body: &'static str, delay: Duration) -> SocketAddr {
+    serve_stub(move |_req| async move {
+        tokio::time::sleep(delay).await;
+        Response::new(Full::new(Bytes::from(body)))
+    })
+    .await
+}
+
+/// Switches every request to the `echo` protocol and echoes the upgraded connection.
+pub async fn echo_upgrade() -> SocketAddr {
+    serve_stub(|mut req: Request<Incoming>| async move {
+        assert_eq!(req.headers()[UPGRADE], "echo");
+        tokio::spawn(async move {
+            let upgraded = hyper::upgrade::on(&mut req).await.unwrap();
+            let (mut read, mut write) = tokio::io::split(TokioIo::new(upgraded));
+            let _ = tokio::io::copy(&mut read, &mut write).await;
+        });
+
+        Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(CONNECTION, "upgrade")
+            .header(UPGRADE, "echo")
+            .body(Empty::<Bytes>::new())
+            .unwrap()
+    })
+    .await
+}
+
+/// An address nothing listens on.
+pub async fn closed_port() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    listener.local_addr().unwrap()
+}
@@ -0,0 +1,306 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        net::SocketAddr,
+        pin::Pin,
+        sync::{Arc, Mutex},
+        task::{Context, Poll},
+        time::{Duration, Instant},
+    };
+
+    use http_body_util::{BodyExt, Full};
+    use hyper::{
+        body::{Body, Bytes, Frame, Incoming},
+        server::conn::http2,
+        service::service_fn,
+        HeaderMap, Request, Response, StatusCode,
+    };
+    use hyper_util::rt::{TokioExecutor, TokioIo};
+    use oxidegate::{
+        load_balancer::factory::LoadBalancerFactory,
+        metrics::metrics,
+        proxy_service::{
+            gateway_body::GatewayBody,
+            gateway_error::GatewayError,
+            grpc::{self, Code},
+            proxy_bridge::ProxyBridge,
+            proxy_handler::{build_client, ProxyHandler},
+        },
+        types::{Backend, Frontend, ServerSettings, Timeouts, UpstreamProtocol},
+    };
+    use tokio::net::TcpListener;
+
+    use crate::common::serve_h2c_stub;
+
+    fn grpc_headers(timeout: Option<&str>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", "application/grpc+proto".parse().unwrap());
+        if let Some(timeout) = timeout {
+            headers.insert("grpc-timeout", timeout.parse().unwrap());
+        }
+        headers
+    }
+
+    /// Serves the gateway over h2c with a single frontend for `services`, `settings` being added
+    /// to the backend.
+    async fn gateway(services: &[&str], backend: &str, settings: &str) -> SocketAddr {
+        let backend: Backend = serde_yaml::from_str(&format!(
+            "{{name: grpc, protocol: H2c, servers: [{{server: \"{}\"}}], timeouts: {{request_timeout_ms: 5000}}{}}}",
+            backend, settings
+        ))
+        .unwrap();
+        let mut frontend: Frontend = serde_yaml::from_str("{backend: grpc}").unwrap();
+        frontend.grpc_services = services.iter().map(|s| s.to_string()).collect();
+
+        let balancer = LoadBalancerFactory::create(backend.lb_algorithm, backend.servers.clone());
+        let handler = ProxyHandler::new(&frontend, &backend, balancer, None).unwrap();
+        let bridge = Arc::new(
+            ProxyBridge::new(
+                Arc::new(vec![(frontend, Arc::new(handler))]),
+                &ServerSettings::default(),
+                None,
+                None,
+            )
+            .unwrap(),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, peer) = listener.accept().await.unwrap();
+                let bridge = bridge.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req| {
+                        let bridge = bridge.clone();
+                        async move { Ok::<_, Infallible>(bridge.determine(req, peer).await) }
+                    });
+                    let _ = http2::Builder::new(TokioExecutor::new())
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        addr
+    }
+
+    /// An h2c upstream answering after `delay`.
+    async fn slow_backend(delay: Duration) -> SocketAddr {
+        serve_h2c_stub(move |_req| async move {
+            tokio::time::sleep(delay).await;
+            Response::new(Full::new(Bytes::new()))
+        })
+        .await
+    }
+
+    /// An h2c upstream answering `503` after `delay`, recording the `grpc-timeout` it receives.
+    async fn failing_backend(delay: Duration) -> (SocketAddr, Arc<Mutex<Vec<String>>>) {
+        let timeouts = Arc::new(Mutex::new(Vec::new()));
+
+        let seen = timeouts.clone();
+        let addr = serve_h2c_stub(move |req: Request<Incoming>| {
+            let timeout = req.headers().get("grpc-timeout").cloned();
+            seen.lock().unwrap().push(
+                timeout
+                    .map(|timeout| timeout.to_str().unwrap().to_string())
+                    .unwrap_or_default(),
+            );
+            async move {
+                tokio::time::sleep(delay).await;
+                let mut res = Response::new(Full::new(Bytes::new()));
+                *res.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+                res
+            }
+        })
+        .await;
+
+        (addr, timeouts)
+    }
+
+    /// A response body sending one message, then nothing.
+    struct Stalled(Option<Bytes>);
+
+    impl Body for Stalled {
+        type Data = Bytes;
+        type Error = Infallible;
+
+        fn poll_frame(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+            match self.0.take() {
+                Some(data) => Poll::Ready(Some(Ok(Frame::data(data)))),
+                None => Poll::Pending,
+            }
+        }
+    }
+
+    /// An h2c upstream starting a response stream that never ends.
+    async fn streaming_backend() -> SocketAddr {
+        serve_h2c_stub(|_req| async {
+            let mut res = Response::new(Stalled(Some(Bytes::from_static(b"\0\0\0\0\0"))));
+            res.headers_mut()
+                .insert("content-type", "application/grpc".parse().unwrap());
+            res
+        })
+        .await
+    }
+
+    async fn call(
+        gateway: SocketAddr,
+        path: &str,
+        timeout: Option<&str>,
+    ) -> (StatusCode, HeaderMap) {
+        let client = build_client(&Timeouts::default(), UpstreamProtocol::H2c).unwrap();
+        let mut req = Request::builder()
+            .method("POST")
+            .uri(format!("http://{}{}", gateway, path))
+            .body(GatewayBody::Buffered(Full::new(Bytes::from_static(
+                b"\0\0\0\0\0",
+            ))))
+            .unwrap();
+        *req.headers_mut() = grpc_headers(timeout);
+        req.headers_mut().insert("te", "trailers".parse().unwrap());
+
+        let res = client.request(req).await.unwrap();
+        let status = res.status();
+        let trailers = res.into_body().collect().await.unwrap().trailers().cloned();
+        (status, trailers.unwrap_or_default())
+    }
+
+    #[test]
+    fn test_grpc_requests() {
+        assert!(grpc::is_grpc(&grpc_headers(None)));
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", "application/grpc-web".parse().unwrap());
+        assert!(!grpc::is_grpc(&headers));
+
+        assert_eq!(
+            grpc::method("/helloworld.Greeter/SayHello"),
+            Some(("helloworld.Greeter", "SayHello"))
+        );
+        assert_eq!(grpc::method("/helloworld.Greeter"), None);
+        assert_eq!(grpc::method("/a/b/c"), None);
+
+        assert_eq!(
+            grpc::route_path("helloworld.Greeter").unwrap(),
+            "/helloworld.Greeter/*"
+        );
+        assert_eq!(
+            grpc::route_path("helloworld.Greeter/SayHello").unwrap(),
+            "/helloworld.Greeter/SayHello"
+        );
+        assert!(grpc::route_path("/helloworld.Greeter").is_err());
+        assert!(grpc::route_path("helloworld.Greeter/*").is_err());
+        assert!(grpc::route_path("").is_err());
+    }
+
+    #[test]
+    fn test_grpc_timeout() {
+        let timeout = |value| grpc::timeout(&grpc_headers(Some(value)));
+
+        assert_eq!(timeout("100m"), Some(Duration::from_millis(100)));
+        assert_eq!(timeout("2S"), Some(Duration::from_secs(2)));
+        assert_eq!(timeout("1H"), Some(Duration::from_secs(3600)));
+        assert_eq!(timeout("5u"), Some(Duration::from_micros(5)));
+        assert_eq!(timeout("123456789m"), None);
+        assert_eq!(timeout("m"), None);
+        assert_eq!(timeout("10x"), None);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("grpc-timeout", "1S".parse().unwrap());
+        assert_eq!(grpc::timeout(&headers), None);
+
+        assert_eq!(grpc::timeout_value(Duration::from_micros(1500)), "1m");
+        assert_eq!(grpc::timeout_value(Duration::from_secs(2)), "2000m");
+        assert_eq!(grpc::timeout_value(Duration::from_secs(200_000)), "200000S");
+    }
+
+    #[tokio::test]
+    async fn test_deadline_caps_retries() {
+        let (backend, timeouts) = failing_backend(Duration::from_millis(100)).await;
+        let gateway = gateway(
+            &["test.Retry"],
+            &format!("http://{}", backend),
+            ", retry: {max_attempts: 10, status_codes: [503], idempotent_only: false, backoff_base_ms: 50, backoff_max_ms: 50}",
+        )
+        .await;
+
+        let start = Instant::now();
+        let (status, trailers) = call(gateway, "/test.Retry/Call", Some("350m")).await;
+        // The last attempt either got its 503 or ran out of time.
+        match status {
+            StatusCode::OK => assert_eq!(trailers["grpc-status"], "4"),
+            status => assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE),
+        }
+        assert!(start.elapsed() < Duration::from_millis(600));
+
+        // Every attempt is told the time left of the deadline.
+        let timeouts = timeouts.lock().unwrap().clone();
+        assert!((2..=4).contains(&timeouts.len()), "{:?}", timeouts);
+        let millis = timeouts
+            .iter()
+            .map(|timeout| timeout.strip_suffix('m').unwrap().parse::<u64>().unwrap())
+            .collect::<Vec<_>>();
+        assert!(millis[0] <= 350);
+        assert!(millis.windows(2).all(|pair| pair[1] + 100 <= pair[0]));
+    }
+
+    #[tokio::test]
+    async fn test_deadline_ends_response_stream() {
+        let backend = streaming_backend().await;
+        let gateway = gateway(&["test.Stream"], &format!("http://{}", backend), "").await;
+
+        let start = Instant::now();
+        let (status, trailers) = call(gateway, "/test.Stream/Watch", Some("200m")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(trailers["grpc-status"], "4");
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_grpc_error_response() {
+        let res = GatewayError::NoHealthyUpstream.into_grpc_response();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["content-type"], "application/grpc");
+
+        let trailers = res.into_body().collect().await.unwrap().trailers().cloned();
+        let trailers = trailers.unwrap();
+        assert_eq!(trailers["grpc-status"], "14");
+        assert_eq!(
+            trailers["grpc-message"],
+            "No healthy upstream server is available"
+        );
+        assert_eq!(Code::from_headers(&trailers), Some(Code::Unavailable));
+    }
+
+    #[tokio::test]
+    async fn test_gateway_errors_as_grpc_status() {
+        let backend = slow_backend(Duration::from_secs(2)).await;
+        let gateway = gateway(&["test.Slow"], &format!("http://{}", backend), "").await;
+
+        let (status, trailers) = call(gateway, "/test.Slow/Wait", Some("100m")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(trailers["grpc-status"], "4");
+
+        let (status, trailers) = call(gateway, "/test.Other/Call", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(trailers["grpc-status"], "12");
+
+        // The call is recorded once the gateway drops the response body.
+        let counter = r#"oxidegate_grpc_requests_total{service="test.Slow",method="Wait",code="DEADLINE_EXCEEDED"} 1"#;
+        for _ in 0..100 {
+            if metrics().render().contains(counter) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let rendered = metrics().render();
+        assert!(rendered.contains(counter));
+        assert!(!rendered.contains(r#"service="test.Other""#));
+    }
+}
@@ -0,0 +1,207 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, sync::Arc};
+
+    use http_body_util::{BodyExt, Full};
+    use hyper::{
+        body::{Bytes, Incoming},
+        client, Request, Response, StatusCode, Version,
+    };
+    use hyper_util::rt::{TokioExecutor, TokioIo};
+    use oxidegate::{
+        config::Config,
+        gateway::Gateway,
+        proxy_service::gateway_body::GatewayBody,
+        server::{http::serve_http, shutdown::Shutdown},
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use crate::common::serve_stub;
+
+    const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+    /// A backend answering with the method and path it received.
+    async fn echo() -> SocketAddr {
+        serve_stub(|req: Request<Incoming>| async move {
+            let body = format!("{} {}", req.method(), req.uri());
+            Response::new(Full::new(Bytes::from(body)))
+        })
+        .await
+    }
+
+    /// Serves a frontend of the echo backend, with the HTTP/2 settings of `http2`.
+    async fn gateway(http2: &str) -> SocketAddr {
+        let config: Config = serde_yaml::from_str(&format!(
+            "{{server: {{http2: {}}}, frontends: [{{backend: echo}}], backends: [{{name: echo, servers: [{{server: \"http://{}\"}}]}}]}}",
+            http2,
+            echo().await
+        ))
+        .unwrap();
+        let http2 = config.server.http2.clone();
+        let gateway = Arc::new(Gateway::new(config, None, None).unwrap());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _ = serve_http(listener, &gateway, &Shutdown::new(), &http2).await;
+        });
+
+        addr
+    }
+
+    struct Frame {
+        kind: u8,
+        flags: u8,
+        stream: u32,
+        payload: Vec<u8>,
+    }
+
+    async fn read_frame(stream: &mut TcpStream) -> Frame {
+        let mut header = [0; 9];
+        stream.read_exact(&mut header).await.unwrap();
+        let length = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+        let mut payload = vec![0; length];
+        stream.read_exact(&mut payload).await.unwrap();
+
+        Frame {
+            kind: header[3],
+            flags: header[4],
+            stream: u32::from_be_bytes([header[5], header[6], header[7], header[8]]) & 0x7fff_ffff,
+            payload,
+        }
+    }
+
+    /// Sends the client preface and returns the settings of the server's first frame.
+    async fn handshake(stream: &mut TcpStream) -> Vec<(u16, u32)> {
+        stream.write_all(PREFACE).await.unwrap();
+        stream
+            .write_all(&[0, 0, 0, 4, 0, 0, 0, 0, 0])
+            .await
+            .unwrap();
+
+        let settings = read_frame(stream).await;
+        assert_eq!((settings.kind, settings.flags, settings.stream), (4, 0, 0));
+        stream
+            .write_all(&[0, 0, 0, 4, 1, 0, 0, 0, 0])
+            .await
+            .unwrap();
+
+        settings
+            .payload
+            .chunks(6)
+            .map(|setting| {
+                (
+                    u16::from_be_bytes([setting[0], setting[1]]),
+                    u32::from_be_bytes([setting[2], setting[3], setting[4], setting[5]]),
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_prior_knowledge() {
+        let gateway =
+            gateway("{max_concurrent_streams: 7, initial_stream_window_size: 131072}").await;
+
+        let stream = TcpStream::connect(gateway).await.unwrap();
+        let (mut sender, conn) = client::conn::http2::Builder::new(TokioExecutor::new())
+            .handshake(TokioIo::new(stream))
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+
+        let requests = ["/one", "/two?x=1"].map(|path| {
+            let req = Request::builder()
+                .uri(format!("http://{}{}", gateway, path))
+                .body(GatewayBody::Empty)
+                .unwrap();
+            sender.send_request(req)
+        });
+        for (res, path) in requests.into_iter().zip(["/one", "/two?x=1"]) {
+            let res = res.await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(res.version(), Version::HTTP_2);
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(body, format!("GET {}", path));
+        }
+
+        // SETTINGS_MAX_CONCURRENT_STREAMS and SETTINGS_INITIAL_WINDOW_SIZE.
+        let mut stream = TcpStream::connect(gateway).await.unwrap();
+        let settings = handshake(&mut stream).await;
+        assert!(settings.contains(&(0x3, 7)));
+        assert!(settings.contains(&(0x4, 131072)));
+    }
+
+    #[tokio::test]
+    async fn test_upgrade() {
+        let gateway = gateway("{max_concurrent_streams: 7}").await;
+
+        let mut stream = TcpStream::connect(gateway).await.unwrap();
+        stream
+            .write_all(
+                b"GET /upgraded?x=1 HTTP/1.1\r\nHost: example.com\r\n\
+                  Connection: Upgrade, HTTP2-Settings\r\nUpgrade: h2c\r\n\
+                  HTTP2-Settings: AAMAAABkAARAAAAAAAIAAAAA\r\n\r\n",
+            )
+            .await
+            .unwrap();
+
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(stream.read_u8().await.unwrap());
+        }
+        let head = String::from_utf8(head).unwrap().to_ascii_lowercase();
+        assert!(head.starts_with("http/1.1 101 switching protocols\r\n"));
+        assert!(head.contains("upgrade: h2c\r\n"));
+
+        let settings = handshake(&mut stream).await;
+        assert!(settings.contains(&(0x3, 7)));
+
+        // The request that asked for the switch is answered on stream 1.
+        let mut status = None;
+        let mut body = Vec::new();
+        loop {
+            let frame = read_frame(&mut stream).await;
+            match (frame.kind, frame.stream) {
+                (1, 1) => status = frame.payload.first().copied(),
+                (0, 1) => body.extend_from_slice(&frame.payload),
+                _ => continue,
+            }
+            if frame.flags & 0x1 != 0 {
+                break;
+            }
+        }
+
+        // `:status: 200`, indexed in the static table.
+        assert_eq!(status, Some(0x88));
+        assert_eq!(body, b"GET /upgraded?x=1");
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_with_body_stays_on_http1() {
+        let gateway = gateway("{}").await;
+
+        let mut stream = TcpStream::connect(gateway).await.unwrap();
+        stream
+            .write_all(
+                b"POST /form HTTP/1.1\r\nHost: example.com\r\n\
+                  Connection: Upgrade, HTTP2-Settings\r\nUpgrade: h2c\r\n\
+                  HTTP2-Settings: AAMAAABkAARAAAAAAAIAAAAA\r\nContent-Length: 2\r\n\r\nok",
+            )
+            .await
+            .unwrap();
+
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(stream.read_u8().await.unwrap());
+        }
+        assert!(String::from_utf8(head)
+            .unwrap()
+            .starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+}
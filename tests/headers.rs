@@ -0,0 +1,395 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
+
+    use http_body_util::{BodyExt, Empty, Full};
+    use hyper::{
+        body::{Bytes, Incoming},
+        server::conn::http1,
+        service::service_fn,
+        HeaderMap, Request, Response, Version,
+    };
+    use hyper_util::rt::TokioIo;
+    use oxidegate::{
+        load_balancer::factory::LoadBalancerFactory,
+        proxy_service::{
+            forwarded::{Cidr, Forwarder},
+            gateway_body::{ByteCounter, GatewayBody},
+            header_rewriter::{HeaderRewriter, TemplateVars},
+            headers::{accepts_trailers, append_via, strip_hop_by_hop},
+            proxy_bridge::ProxyBridge,
+            proxy_handler::ProxyHandler,
+            request_id,
+        },
+        types::{
+            Backend, ForwardedHeaders, Frontend, HeaderRules, RequestIdFormat, ServerSettings,
+        },
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use crate::common::{closed_port, serve_stub};
+
+    fn forwarder(mode: ForwardedHeaders, trusted_proxies: &[&str]) -> Forwarder {
+        let settings = ServerSettings {
+            enable_https: true,
+            forwarded_headers: mode,
+            trusted_proxies: trusted_proxies.iter().map(|p| p.to_string()).collect(),
+            ..Default::default()
+        };
+
+        Forwarder::new(&settings).unwrap()
+    }
+
+    #[test]
+    fn test_cidr() {
+        let cidr: Cidr = "10.0.0.0/8".parse().unwrap();
+        assert!(cidr.contains("10.1.2.3".parse().unwrap()));
+        assert!(cidr.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!cidr.contains("11.0.0.1".parse().unwrap()));
+
+        let cidr: Cidr = "::1".parse().unwrap();
+        assert!(cidr.contains("::1".parse().unwrap()));
+        assert!(!cidr.contains("127.0.0.1".parse().unwrap()));
+
+        let cidr: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(cidr.contains("192.168.1.1".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("localhost".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn test_untrusted_peer_overwrites_headers() {
+        let forwarder = forwarder(ForwardedHeaders::Both, &["10.0.0.0/8"]);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "1.1.1.1".parse().unwrap());
+        headers.insert("x-forwarded-proto", "http".parse().unwrap());
+        headers.insert("forwarded", "for=1.1.1.1".parse().unwrap());
+
+        forwarder.apply(
+            &mut headers,
+            Some("example.com:8443"),
+            "192.0.2.7:5000".parse().unwrap(),
+        );
+
+        assert_eq!(headers["x-forwarded-for"], "192.0.2.7");
+        assert_eq!(headers["x-forwarded-proto"], "https");
+        assert_eq!(headers["x-forwarded-host"], "example.com:8443");
+        assert_eq!(
+            headers["forwarded"],
+            "for=192.0.2.7;proto=https;host=\"example.com:8443\""
+        );
+    }
+
+    #[test]
+    fn test_trusted_peer_appends_headers() {
+        let forwarder = forwarder(ForwardedHeaders::XForwarded, &["10.0.0.0/8"]);
+
+        let mut headers = HeaderMap::new();
+        headers.append("x-forwarded-for", "203.0.113.1".parse().unwrap());
+        headers.append("x-forwarded-for", "198.51.100.2".parse().unwrap());
+        headers.insert("x-forwarded-proto", "http".parse().unwrap());
+
+        forwarder.apply(
+            &mut headers,
+            Some("example.com"),
+            "10.0.0.5:5000".parse().unwrap(),
+        );
+
+        assert_eq!(
+            headers["x-forwarded-for"],
+            "203.0.113.1, 198.51.100.2, 10.0.0.5"
+        );
+        assert_eq!(headers["x-forwarded-proto"], "http");
+        assert!(headers.get("forwarded").is_none());
+    }
+
+    #[test]
+    fn test_strip_hop_by_hop() {
+        let mut headers = HeaderMap::new();
+        headers.insert("connection", "keep-alive, X-Session".parse().unwrap());
+        headers.insert("keep-alive", "timeout=5".parse().unwrap());
+        headers.insert("x-session", "abc".parse().unwrap());
+        headers.insert("transfer-encoding", "chunked".parse().unwrap());
+        headers.insert("proxy-authorization", "Basic Zm9v".parse().unwrap());
+        headers.insert("upgrade", "h2c".parse().unwrap());
+        headers.insert("te", "trailers".parse().unwrap());
+        headers.insert("trailer", "grpc-status".parse().unwrap());
+        headers.insert("authorization", "Bearer token".parse().unwrap());
+        headers.insert("via", "1.0 edge".parse().unwrap());
+
+        strip_hop_by_hop(&mut headers);
+        append_via(&mut headers, Version::HTTP_11);
+
+        assert_eq!(headers.len(), 3);
+        assert_eq!(headers["authorization"], "Bearer token");
+        assert_eq!(headers["trailer"], "grpc-status");
+        assert_eq!(headers["via"], "1.0 edge, 1.1 oxidegate");
+    }
+
+    #[test]
+    fn test_accepts_trailers() {
+        let mut headers = HeaderMap::new();
+        assert!(!accepts_trailers(&headers));
+
+        headers.insert("te", "gzip;q=0.5".parse().unwrap());
+        assert!(!accepts_trailers(&headers));
+
+        headers.append("te", "deflate, Trailers".parse().unwrap());
+        assert!(accepts_trailers(&headers));
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let forwarder = forwarder(ForwardedHeaders::XForwarded, &["10.0.0.0/8"]);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            "6.6.6.6, 203.0.113.1, 10.0.0.9".parse().unwrap(),
+        );
+
+        assert_eq!(
+            forwarder.client_ip(&headers, "10.0.0.5:5000".parse().unwrap()),
+            "203.0.113.1".parse::<std::net::IpAddr>().unwrap()
+        );
+        assert_eq!(
+            forwarder.client_ip(&headers, "192.0.2.7:5000".parse().unwrap()),
+            "192.0.2.7".parse::<std::net::IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_header_rewriter() {
+        let backend: HeaderRules = serde_yaml::from_str(
+            r#"
+            remove: ["Server", "X-Powered-By"]
+            set:
+              - name: Strict-Transport-Security
+                value: "max-age=63072000"
+            "#,
+        )
+        .unwrap();
+        let frontend: HeaderRules = serde_yaml::from_str(
+            r#"
+            add:
+              - name: X-Internal-Route
+                value: "${route} for ${client_ip} (${request_id})"
+            "#,
+        )
+        .unwrap();
+        let rewriter = HeaderRewriter::new([&backend, &frontend]).unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("server", "nginx".parse().unwrap());
+        headers.insert("x-powered-by", "php".parse().unwrap());
+        headers.insert("strict-transport-security", "max-age=0".parse().unwrap());
+
+        let vars = TemplateVars {
+            client_ip: "192.0.2.7".parse().unwrap(),
+            request_id: Some("abc"),
+            route: Some("api"),
+        };
+        rewriter.apply(&mut headers, &vars);
+
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers["strict-transport-security"], "max-age=63072000");
+        assert_eq!(headers["x-internal-route"], "api for 192.0.2.7 (abc)");
+
+        let invalid: HeaderRules = serde_yaml::from_str(
+            r#"
+            set:
+              - name: X-Test
+                value: "${unknown}"
+            "#,
+        )
+        .unwrap();
+        assert!(HeaderRewriter::new([&invalid]).is_err());
+    }
+
+    #[test]
+    fn test_request_id() {
+        let uuid = request_id::generate(RequestIdFormat::Uuid);
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+        assert!("89ab".contains(&uuid[19..20]));
+        assert_ne!(uuid, request_id::generate(RequestIdFormat::Uuid));
+
+        let ulid = request_id::generate(RequestIdFormat::Ulid);
+        assert_eq!(ulid.len(), 26);
+        assert!(ulid.bytes().all(|b| b.is_ascii_alphanumeric()));
+        assert!(request_id::is_valid(&ulid));
+
+        assert!(!request_id::is_valid(""));
+        assert!(!request_id::is_valid("has space"));
+        assert!(!request_id::is_valid(&"a".repeat(129)));
+    }
+
+    #[tokio::test]
+    async fn test_request_id_scope() {
+        assert_eq!(request_id::current(), None);
+
+        let id = request_id::scope("abc".to_string(), async { request_id::current() }).await;
+        assert_eq!(id.as_deref(), Some("abc"));
+    }
+
+    #[tokio::test]
+    async fn test_scoped_body_carries_request_id() {
+        /// Records the request id seen while the body is polled.
+        #[derive(Default)]
+        struct Seen(Mutex<Option<String>>);
+
+        impl ByteCounter for Seen {
+            fn add(&self, _bytes: usize) {
+                *self.0.lock().unwrap() = request_id::current();
+            }
+        }
+
+        let seen = Arc::new(Seen::default());
+        let body = GatewayBody::Buffered(Full::new(Bytes::from("data")))
+            .counted(seen.clone())
+            .scoped("abc");
+
+        body.collect().await.unwrap();
+        assert_eq!(seen.0.lock().unwrap().as_deref(), Some("abc"));
+        assert_eq!(request_id::current(), None);
+    }
+
+    /// Answers with the request headers, prefixed with `seen-`.
+    async fn echo_headers() -> SocketAddr {
+        serve_stub(|req: Request<Incoming>| async move {
+            let mut res = Response::new(Empty::<Bytes>::new());
+            for (name, value) in req.headers() {
+                let name = format!("seen-{}", name);
+                res.headers_mut().append(
+                    name.parse::<hyper::header::HeaderName>().unwrap(),
+                    value.clone(),
+                );
+            }
+            res
+        })
+        .await
+    }
+
+    /// Serves a frontend of `backend` through a `ProxyBridge`, for a single connection.
+    async fn gateway(backend: &str, settings: ServerSettings) -> SocketAddr {
+        let backend: Backend = serde_yaml::from_str(backend).unwrap();
+        let frontend: Frontend = serde_yaml::from_str("{backend: echo}").unwrap();
+        let balancer = LoadBalancerFactory::create(backend.lb_algorithm, backend.servers.clone());
+        let handler = ProxyHandler::new(&frontend, &backend, balancer, None).unwrap();
+        let bridge = Arc::new(
+            ProxyBridge::new(
+                Arc::new(vec![(frontend, Arc::new(handler))]),
+                &settings,
+                None,
+                None,
+            )
+            .unwrap(),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, peer) = listener.accept().await.unwrap();
+            let service = service_fn(move |req| {
+                let bridge = bridge.clone();
+                async move { Ok::<_, Infallible>(bridge.determine(req, peer).await) }
+            });
+            let _ = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_client_cannot_strip_gateway_headers() {
+        let backend_addr = echo_headers().await;
+        let settings = ServerSettings {
+            forwarded_headers: ForwardedHeaders::Both,
+            ..Default::default()
+        };
+        let gateway_addr = gateway(
+            &format!(
+                "{{name: echo, servers: [{{server: \"http://{}\"}}]}}",
+                backend_addr
+            ),
+            settings,
+        )
+        .await;
+
+        let mut client = TcpStream::connect(gateway_addr).await.unwrap();
+        client
+            .write_all(
+                b"GET / HTTP/1.1\r\nHost: gateway\r\n\
+                  Connection: close, x-forwarded-for, forwarded, x-request-id, x-session\r\n\
+                  X-Forwarded-For: 10.0.0.1\r\nX-Session: abc\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        let response = response.to_ascii_lowercase();
+
+        assert!(response.starts_with("http/1.1 200"), "{}", response);
+        assert!(
+            response.contains("seen-x-forwarded-for: 127.0.0.1\r\n"),
+            "{}",
+            response
+        );
+        assert!(
+            response.contains("seen-forwarded: for=127.0.0.1"),
+            "{}",
+            response
+        );
+        assert!(response.contains("seen-x-request-id: "), "{}", response);
+        assert!(!response.contains("seen-x-session"), "{}", response);
+        assert!(!response.contains("10.0.0.1"), "{}", response);
+    }
+
+    #[tokio::test]
+    async fn test_response_rules_apply_to_gateway_errors() {
+        let closed_addr = closed_port().await;
+
+        let gateway_addr = gateway(
+            &format!(
+                "{{name: echo, servers: [{{server: \"http://{}\"}}], \
+                 response_headers: {{set: [{{name: X-Route-Id, value: \"${{request_id}}\"}}]}}}}",
+                closed_addr
+            ),
+            ServerSettings::default(),
+        )
+        .await;
+
+        let mut client = TcpStream::connect(gateway_addr).await.unwrap();
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: gateway\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        let response = response.to_ascii_lowercase();
+
+        assert!(response.starts_with("http/1.1 502"), "{}", response);
+        let request_id = response
+            .lines()
+            .find_map(|line| line.strip_prefix("x-request-id: "))
+            .unwrap();
+        assert!(
+            response.contains(&format!("x-route-id: {}\r\n", request_id)),
+            "{}",
+            response
+        );
+    }
+}
@@ -1,10 +1,12 @@
 #[cfg(test)]
 mod tests {
     use oxidegate::{
-        types::BackendServer, LbAlgorithm, LeastConnectionsStrategy, LoadBalancer,
-        LoadBalancerFactory, RoundRobinStrategy, WeightedRoundRobin,
+        load_balancer::outlier_detection::{Outcome, OutlierDetector},
+        types::{BackendServer, OutlierDetection},
+        HealthRegistry, LbAlgorithm, LeastConnectionsStrategy, LoadBalancer, LoadBalancerFactory,
+        RoundRobinStrategy, WeightedRoundRobin,
     };
-    use std::sync::{atomic::Ordering, Arc};
+    use std::sync::Arc;
 
     #[tokio::test]
     async fn test_least_connections_strategy() {
@@ -15,17 +17,17 @@ mod tests {
         ];
         let strategy = LeastConnectionsStrategy::new(servers.clone());
 
-        for (_, connections) in &strategy.servers {
-            assert_eq!(connections.load(Ordering::Relaxed), 0);
+        for (_, connections) in strategy.connections() {
+            assert_eq!(connections, 0);
         }
 
         let selected = strategy.next().await.unwrap();
         assert_eq!(selected.server, "server1");
-        assert_eq!(strategy.servers[0].1.load(Ordering::Relaxed), 1);
+        assert_eq!(strategy.connections()[0].1, 1);
 
         let selected = strategy.next().await.unwrap();
         assert_eq!(selected.server, "server2");
-        assert_eq!(strategy.servers[1].1.load(Ordering::Relaxed), 1);
+        assert_eq!(strategy.connections()[1].1, 1);
 
         match Arc::try_unwrap(selected) {
             Ok(lb) => (lb.cleanup_fn)(),
@@ -34,11 +36,11 @@ mod tests {
 
         let selected = strategy.next().await.unwrap();
         assert_eq!(selected.server, "server3");
-        assert_eq!(strategy.servers[2].1.load(Ordering::Relaxed), 1);
+        assert_eq!(strategy.connections()[2].1, 1);
 
         let selected = strategy.next().await.unwrap();
         assert_eq!(selected.server, "server1");
-        assert_eq!(strategy.servers[0].1.load(Ordering::Relaxed), 2);
+        assert_eq!(strategy.connections()[0].1, 2);
     }
 
     #[tokio::test]
@@ -121,4 +123,204 @@ mod tests {
         let selected = least_connections_lb.next().await.unwrap();
         assert_eq!(selected.server, "server2");
     }
+
+    #[tokio::test]
+    async fn test_strategies_skip_unhealthy_servers() {
+        let servers = vec![
+            BackendServer {
+                server: "server1".to_string(),
+                weight: Some(1),
+            },
+            BackendServer {
+                server: "server2".to_string(),
+                weight: Some(2),
+            },
+        ];
+
+        for algorithm in [
+            LbAlgorithm::RoundRobin,
+            LbAlgorithm::LeastConnections,
+            LbAlgorithm::WeightedRoundRobin,
+        ] {
+            let lb = LoadBalancerFactory::create(algorithm, servers.clone());
+            lb.health().set_healthy("server2", false);
+
+            for _ in 0..4 {
+                let selected = lb.next().await.unwrap();
+                assert_eq!(selected.server, "server1");
+            }
+
+            lb.health().set_healthy("server1", false);
+            assert!(lb.next().await.is_none());
+
+            lb.health().set_healthy("server2", true);
+            let selected = lb.next().await.unwrap();
+            assert_eq!(selected.server, "server2");
+        }
+    }
+
+    #[test]
+    fn test_health_thresholds() {
+        let servers = vec!["server1".to_string()];
+        let registry = HealthRegistry::new(&servers);
+        let (_, health) = registry.servers().remove(0);
+
+        assert!(!health.record_failure(2));
+        assert!(registry.is_healthy("server1"));
+        assert!(health.record_failure(2));
+        assert!(!registry.is_healthy("server1"));
+
+        assert!(!health.record_success(2));
+        assert!(!registry.is_healthy("server1"));
+        assert!(health.record_success(2));
+        assert!(registry.is_healthy("server1"));
+    }
+
+    #[tokio::test]
+    async fn test_outlier_detection_ejects_failing_servers() {
+        let servers = vec![
+            "server1".to_string(),
+            "server2".to_string(),
+            "server3".to_string(),
+        ];
+        let strategy = RoundRobinStrategy::new(servers);
+        let detector = OutlierDetector::new(
+            "backend".to_string(),
+            OutlierDetection {
+                consecutive_failures: 2,
+                failure_rate_threshold: None,
+                window_ms: 30_000,
+                min_requests: 10,
+                base_ejection_ms: 30_000,
+                max_ejection_ms: 300_000,
+                max_ejection_percent: 50,
+            },
+            strategy.health(),
+        );
+
+        detector.report("server1", Outcome::Failure);
+        assert!(strategy.health().is_healthy("server1"));
+        detector.report("server1", Outcome::Failure);
+        assert!(!strategy.health().is_healthy("server1"));
+
+        for _ in 0..4 {
+            let selected = strategy.next().await.unwrap();
+            assert_ne!(selected.server, "server1");
+        }
+
+        detector.report("server2", Outcome::Failure);
+        detector.report("server2", Outcome::Failure);
+        assert!(strategy.health().is_healthy("server2"));
+    }
+
+    #[test]
+    fn test_outlier_detection_keeps_an_available_server() {
+        let servers = vec!["server1".to_string(), "server2".to_string()];
+        let strategy = RoundRobinStrategy::new(servers);
+        let detector = OutlierDetector::new(
+            "backend".to_string(),
+            OutlierDetection {
+                consecutive_failures: 1,
+                failure_rate_threshold: None,
+                window_ms: 30_000,
+                min_requests: 10,
+                base_ejection_ms: 30_000,
+                max_ejection_ms: 300_000,
+                max_ejection_percent: 100,
+            },
+            strategy.health(),
+        );
+
+        // server2 is the only available server, whether it is unhealthy or draining.
+        strategy.health().set_healthy("server1", false);
+        detector.report("server2", Outcome::Failure);
+        assert!(!strategy.health().get("server2").unwrap().is_ejected());
+
+        strategy.health().set_healthy("server1", true);
+        strategy.health().get("server1").unwrap().set_draining(true);
+        detector.report("server2", Outcome::Failure);
+        assert!(!strategy.health().get("server2").unwrap().is_ejected());
+
+        strategy
+            .health()
+            .get("server1")
+            .unwrap()
+            .set_draining(false);
+        detector.report("server2", Outcome::Failure);
+        assert!(strategy.health().get("server2").unwrap().is_ejected());
+    }
+
+    #[tokio::test]
+    async fn test_set_servers_keeps_connections() {
+        let strategy =
+            LeastConnectionsStrategy::new(vec!["server1".to_string(), "server2".to_string()]);
+
+        let first = strategy.next().await.unwrap();
+        let second = strategy.next().await.unwrap();
+        assert_eq!(first.server, "server1");
+        assert_eq!(second.server, "server2");
+
+        strategy.set_servers(vec![
+            BackendServer {
+                server: "server1".to_string(),
+                weight: None,
+            },
+            BackendServer {
+                server: "server3".to_string(),
+                weight: None,
+            },
+        ]);
+        assert_eq!(
+            strategy.connections(),
+            vec![("server1".to_string(), 1), ("server3".to_string(), 0)]
+        );
+        assert!(strategy.health().get("server2").is_none());
+
+        // Requests to a removed server complete without touching the remaining counters.
+        drop(second);
+        drop(first);
+        assert_eq!(
+            strategy.connections(),
+            vec![("server1".to_string(), 0), ("server3".to_string(), 0)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_draining_and_reweighting() {
+        let servers = vec![
+            BackendServer {
+                server: "server1".to_string(),
+                weight: Some(1),
+            },
+            BackendServer {
+                server: "server2".to_string(),
+                weight: Some(1),
+            },
+        ];
+
+        for algorithm in [
+            LbAlgorithm::RoundRobin,
+            LbAlgorithm::LeastConnections,
+            LbAlgorithm::WeightedRoundRobin,
+        ] {
+            let lb = LoadBalancerFactory::create(algorithm, servers.clone());
+            lb.health().get("server1").unwrap().set_draining(true);
+
+            for _ in 0..4 {
+                assert_eq!(lb.next().await.unwrap().server, "server2");
+            }
+            assert!(lb.health().is_healthy("server1"));
+        }
+
+        let lb = LoadBalancerFactory::create(LbAlgorithm::WeightedRoundRobin, servers.clone());
+        let mut reweighted = lb.servers();
+        reweighted[1].weight = Some(3);
+        lb.set_servers(reweighted);
+
+        let mut selected = Vec::new();
+        for _ in 0..4 {
+            selected.push(lb.next().await.unwrap().server.clone());
+        }
+        assert_eq!(selected, vec!["server1", "server2", "server2", "server2"]);
+    }
 }
@@ -0,0 +1,150 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+    use hyper::{Method, Request, StatusCode};
+    use oxidegate::{
+        config::Config,
+        gateway::Gateway,
+        metrics::{metrics, RequestLabels},
+        proxy_service::{gateway_body::GatewayBody, proxy_handler::build_client},
+        server::{http::serve_http, shutdown::Shutdown},
+        types::{Timeouts, UpstreamProtocol},
+        LeastConnectionsStrategy, LoadBalancer,
+    };
+    use tokio::{net::TcpListener, sync::Mutex};
+
+    use crate::common::{closed_port, stub};
+
+    /// The metrics are global, so tests rendering them take turns.
+    static METRICS: Mutex<()> = Mutex::const_new(());
+
+    /// Each request opens its own connection, so every one is counted by the listener.
+    async fn get(gateway: SocketAddr, path: &str) -> StatusCode {
+        let client = build_client(&Timeouts::default(), UpstreamProtocol::Http1).unwrap();
+        let req = Request::builder()
+            .uri(format!("http://{}{}", gateway, path))
+            .body(GatewayBody::Empty)
+            .unwrap();
+        client.request(req).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_metrics_of_proxied_requests() {
+        let _metrics = METRICS.lock().await;
+
+        let ok = stub("ok", Duration::ZERO).await;
+        let slow = stub("ok", Duration::from_secs(5)).await;
+        let held = stub("ok", Duration::from_millis(500)).await;
+        let dead = closed_port().await;
+
+        let config: Config = serde_yaml::from_str(&format!(
+            r#"
+            frontends:
+              - {{path_prefixes: ["/ok/*"], backend: ok}}
+              - {{path_prefixes: ["/slow/*"], backend: slow}}
+              - {{path_prefixes: ["/held/*"], backend: held}}
+              - {{path_prefixes: ["/dead/*"], backend: dead}}
+            backends:
+              - {{name: ok, lb_algorithm: LeastConnections, servers: [{{server: "http://{}"}}]}}
+              - {{name: slow, servers: [{{server: "http://{}"}}], timeouts: {{request_timeout_ms: 100}}}}
+              - {{name: held, lb_algorithm: LeastConnections, servers: [{{server: "http://{}"}}]}}
+              - {{name: dead, servers: [{{server: "http://{}"}}]}}
+            "#,
+            ok, slow, held, dead
+        ))
+        .unwrap();
+        let http2 = config.server.http2.clone();
+        let gateway = Arc::new(Gateway::new(config, None, None).unwrap());
+        let shutdown = Shutdown::new();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        {
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move {
+                let _ = serve_http(listener, &gateway, &shutdown, &http2).await;
+            });
+        }
+
+        assert_eq!(get(addr, "/ok/").await, StatusCode::OK);
+        assert_eq!(get(addr, "/slow/").await, StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(get(addr, "/dead/").await, StatusCode::BAD_GATEWAY);
+
+        let in_flight = tokio::spawn(get(addr, "/held/"));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let rendered = metrics().render();
+        assert_eq!(in_flight.await.unwrap(), StatusCode::OK);
+        shutdown.trigger();
+
+        assert!(rendered.contains(&format!(
+            r#"oxidegate_upstream_active_connections{{backend="held",server="http://{}"}} 1"#,
+            held
+        )));
+        assert!(rendered.contains(&format!(
+            r#"oxidegate_upstream_active_connections{{backend="ok",server="http://{}"}} 0"#,
+            ok
+        )));
+        assert!(rendered.contains(&format!(
+            r#"oxidegate_upstream_errors_total{{backend="slow",server="http://{}",kind="timeout"}} 1"#,
+            slow
+        )));
+        assert!(rendered.contains(&format!(
+            r#"oxidegate_upstream_errors_total{{backend="dead",server="http://{}",kind="connect"}} 1"#,
+            dead
+        )));
+        assert!(rendered.contains(r#"oxidegate_timeouts_total{kind="request"} 1"#));
+        assert!(rendered.contains(r#"oxidegate_connections_accepted_total{listener="http"} 4"#));
+        assert!(rendered.contains("oxidegate_requests_in_flight 1"));
+    }
+
+    #[tokio::test]
+    async fn test_render_metrics() {
+        let _metrics = METRICS.lock().await;
+
+        let balancer: Arc<dyn LoadBalancer> = Arc::new(LeastConnectionsStrategy::new(vec![
+            "http://10.0.0.1".to_string(),
+        ]));
+        metrics().set_balancers(vec![("api".to_string(), balancer.clone())]);
+        let _selected = balancer.next().await;
+
+        let labels = RequestLabels::new(
+            Some("api"),
+            Some("api"),
+            Some("http://10.0.0.1"),
+            &Method::GET,
+            StatusCode::NOT_FOUND,
+        );
+        metrics().observe_request(labels.clone(), Duration::from_millis(20));
+        metrics().observe_request(labels, Duration::from_secs(20));
+        metrics().upstream_error("api", "http://10.0.0.1", "connect");
+        metrics().connection_accepted("https");
+
+        let in_flight = metrics().request_started();
+        let rendered = metrics().render();
+        drop(in_flight);
+
+        let labels =
+            r#"frontend="api",backend="api",server="http://10.0.0.1",method="GET",status="4xx""#;
+        assert!(rendered.contains(&format!("oxidegate_requests_total{{{}}} 2", labels)));
+        assert!(rendered.contains(&format!(
+            "oxidegate_request_duration_seconds_bucket{{{},le=\"0.025\"}} 1",
+            labels
+        )));
+        assert!(rendered.contains(&format!(
+            "oxidegate_request_duration_seconds_bucket{{{},le=\"+Inf\"}} 2",
+            labels
+        )));
+        assert!(rendered.contains("oxidegate_requests_in_flight 1"));
+        assert!(rendered.contains(
+            r#"oxidegate_upstream_active_connections{backend="api",server="http://10.0.0.1"} 1"#
+        ));
+        assert!(rendered.contains(
+            r#"oxidegate_upstream_errors_total{backend="api",server="http://10.0.0.1",kind="connect"} 1"#
+        ));
+        assert!(rendered.contains(r#"oxidegate_connections_accepted_total{listener="https"} 1"#));
+        assert!(rendered.contains("# TYPE oxidegate_request_duration_seconds histogram"));
+    }
+}
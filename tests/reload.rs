@@ -0,0 +1,211 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
+
+    use http_body_util::BodyExt;
+    use hyper::{body::Bytes, Request};
+    use oxidegate::{
+        config::Config,
+        gateway::Gateway,
+        proxy_service::{gateway_body::GatewayBody, proxy_handler::build_client},
+        server::{http::serve_http, shutdown::Shutdown},
+        types::{BackendServer, Timeouts, UpstreamProtocol},
+    };
+    use tokio::net::TcpListener;
+
+    use crate::common::stub;
+
+    fn config(servers: &[SocketAddr]) -> String {
+        let servers = servers
+            .iter()
+            .map(|addr| format!("{{server: \"http://{}\"}}", addr))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!(
+            "{{frontends: [{{backend: api}}], backends: [{{name: api, servers: [{}]}}]}}",
+            servers
+        )
+    }
+
+    /// A config file for one test, removed when dropped.
+    struct ConfigFile(PathBuf);
+
+    impl ConfigFile {
+        fn new(name: &str) -> Self {
+            Self(std::env::temp_dir().join(format!(
+                "oxidegate-reload-{}-{}.yaml",
+                std::process::id(),
+                name
+            )))
+        }
+
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+
+        fn write(&self, contents: &str) {
+            std::fs::write(&self.0, contents).unwrap();
+        }
+    }
+
+    impl Drop for ConfigFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    /// Serves a gateway running `config` over HTTP.
+    async fn gateway(config: &str) -> (Arc<Gateway>, SocketAddr) {
+        let config: Config = serde_yaml::from_str(config).unwrap();
+        let http2 = config.server.http2.clone();
+        let gateway = Arc::new(Gateway::new(config, None, None).unwrap());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let serving = gateway.clone();
+        tokio::spawn(async move {
+            let _ = serve_http(listener, &serving, &Shutdown::new(), &http2).await;
+        });
+
+        (gateway, addr)
+    }
+
+    async fn get(gateway: SocketAddr) -> Bytes {
+        let client = build_client(&Timeouts::default(), UpstreamProtocol::Http1).unwrap();
+        let req = Request::builder()
+            .uri(format!("http://{}/", gateway))
+            .body(GatewayBody::Empty)
+            .unwrap();
+        let res = client.request(req).await.unwrap();
+        res.into_body().collect().await.unwrap().to_bytes()
+    }
+
+    fn servers(gateway: &Gateway) -> Vec<String> {
+        gateway
+            .current()
+            .balancer("api")
+            .unwrap()
+            .servers()
+            .into_iter()
+            .map(|server| server.server)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_reload_swaps_config() {
+        let one = stub("one", Duration::ZERO).await;
+        let two = stub("two", Duration::ZERO).await;
+        let (gateway, addr) = gateway(&config(&[one])).await;
+        assert_eq!(get(addr).await, "one");
+
+        let file = ConfigFile::new("swap");
+        file.write(&config(&[two]));
+        gateway.reload_from(file.path()).await;
+
+        assert_eq!(get(addr).await, "two");
+        assert_eq!(servers(&gateway), [format!("http://{}", two)]);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_config_keeps_current_generation() {
+        let one = stub("one", Duration::ZERO).await;
+        let (gateway, addr) = gateway(&config(&[one])).await;
+        let current = gateway.current();
+
+        let file = ConfigFile::new("invalid");
+        for invalid in [
+            "frontends: [",
+            "{frontends: [{backend: missing}], backends: []}",
+            "{frontends: [{backend: api}], backends: [{name: api, servers: [{server: \"ftp://x\"}]}]}",
+        ] {
+            file.write(invalid);
+            gateway.reload_from(file.path()).await;
+
+            assert!(Arc::ptr_eq(&current, &gateway.current()));
+            assert_eq!(get(addr).await, "one");
+        }
+
+        gateway.reload_from("/nonexistent/oxidegate.yaml").await;
+        assert!(Arc::ptr_eq(&current, &gateway.current()));
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_requests_finish_on_old_generation() {
+        let old = stub("old", Duration::from_millis(300)).await;
+        let new = stub("new", Duration::ZERO).await;
+        let (gateway, addr) = gateway(&config(&[old])).await;
+
+        let in_flight = tokio::spawn(get(addr));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let file = ConfigFile::new("in-flight");
+        file.write(&config(&[new]));
+        gateway.reload_from(file.path()).await;
+
+        assert_eq!(get(addr).await, "new");
+        assert_eq!(in_flight.await.unwrap(), "old");
+    }
+
+    #[tokio::test]
+    async fn test_admin_changes_survive_reload_of_same_servers() {
+        let one = stub("one", Duration::ZERO).await;
+        let two = stub("two", Duration::ZERO).await;
+        let three = stub("three", Duration::ZERO).await;
+        let (gateway, _) = gateway(&config(&[one])).await;
+
+        // As done by the admin API.
+        {
+            let _servers = gateway.lock_servers();
+            let balancer = gateway.current().balancer("api").unwrap().clone();
+            let mut servers = balancer.servers();
+            servers.push(BackendServer {
+                server: format!("http://{}", two),
+                weight: None,
+            });
+            balancer.set_servers(servers);
+        }
+
+        let file = ConfigFile::new("admin");
+        file.write(&config(&[one]));
+        gateway.reload_from(file.path()).await;
+        assert_eq!(
+            servers(&gateway),
+            [format!("http://{}", one), format!("http://{}", two)]
+        );
+
+        // Servers changed in the config replace the ones changed through the API.
+        file.write(&config(&[three]));
+        gateway.reload_from(file.path()).await;
+        assert_eq!(servers(&gateway), [format!("http://{}", three)]);
+    }
+
+    #[tokio::test]
+    async fn test_removed_health_check_marks_servers_healthy() {
+        let one = stub("one", Duration::ZERO).await;
+        let server = format!("http://{}", one);
+        // The stub answers 200, which this check does not expect.
+        let (gateway, addr) = gateway(&format!(
+            "{{frontends: [{{backend: api}}], backends: [{{name: api, servers: [{{server: \"{}\"}}], health_check: {{interval_ms: 20, unhealthy_threshold: 1, expected_status: {{min: 204, max: 204}}}}}}]}}",
+            server
+        ))
+        .await;
+
+        let health = gateway.current().balancer("api").unwrap().health();
+        for _ in 0..100 {
+            if !health.is_healthy(&server) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(!health.is_healthy(&server));
+
+        let file = ConfigFile::new("health-check");
+        file.write(&config(&[one]));
+        gateway.reload_from(file.path()).await;
+
+        assert!(health.is_healthy(&server));
+        assert_eq!(get(addr).await, "one");
+    }
+}
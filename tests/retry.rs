@@ -0,0 +1,304 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex, OnceLock,
+        },
+        time::Duration,
+    };
+
+    use http_body_util::{BodyExt, Full};
+    use hyper::{
+        body::{Bytes, Incoming},
+        server::conn::http1,
+        service::service_fn,
+        Method, Request, Response, StatusCode,
+    };
+    use hyper_util::rt::TokioIo;
+    use oxidegate::{
+        load_balancer::factory::{LoadBalancer, LoadBalancerFactory},
+        proxy_service::{
+            context::RequestContext,
+            gateway_body::GatewayBody,
+            proxy_handler::{backoff, build_client, ProxyHandler},
+        },
+        types::{Backend, Frontend, Retry, Timeouts, UpstreamProtocol},
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use crate::common::{closed_port, serve_h2c_stub, serve_stub};
+
+    /// A backend answering `status` with the request body, counting its requests.
+    async fn counting_stub(
+        status: StatusCode,
+        on_request: impl Fn() + Clone + Send + Sync + 'static,
+    ) -> (SocketAddr, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+
+        let counter = hits.clone();
+        let addr = serve_stub(move |req: Request<Incoming>| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            on_request();
+            async move {
+                let body = req.into_body().collect().await.unwrap().to_bytes();
+                let mut res = Response::new(Full::new(body));
+                *res.status_mut() = status;
+                res
+            }
+        })
+        .await;
+
+        (addr, hits)
+    }
+
+    fn retry_backend(servers: &[SocketAddr], retry: &str) -> (Backend, Arc<dyn LoadBalancer>) {
+        let servers = servers
+            .iter()
+            .map(|addr| format!("{{server: \"http://{}\"}}", addr))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let backend: Backend = serde_yaml::from_str(&format!(
+            "{{name: retry, servers: [{}], retry: {}}}",
+            servers, retry
+        ))
+        .unwrap();
+        let balancer = LoadBalancerFactory::create(backend.lb_algorithm, backend.servers.clone());
+        (backend, balancer)
+    }
+
+    /// Serves `backend` over HTTP/1.1.
+    async fn serve(backend: &Backend, balancer: Arc<dyn LoadBalancer>) -> SocketAddr {
+        let frontend: Frontend = serde_yaml::from_str("{backend: retry}").unwrap();
+        let handler = Arc::new(ProxyHandler::new(&frontend, backend, balancer, None).unwrap());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req| {
+                        let handler = handler.clone();
+                        async move {
+                            let ctx = RequestContext {
+                                client_ip: "127.0.0.1".parse().unwrap(),
+                                request_id: "test".to_string(),
+                                stats: Default::default(),
+                                trace: None,
+                            };
+                            let res = match handler.handle(req, &ctx).await {
+                                Ok(res) => res,
+                                Err(e) => e.into_response(Default::default()),
+                            };
+                            Ok::<_, Infallible>(res)
+                        }
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        addr
+    }
+
+    async fn request(
+        gateway: SocketAddr,
+        method: Method,
+        body: GatewayBody,
+    ) -> (StatusCode, Bytes) {
+        let client = build_client(&Timeouts::default(), UpstreamProtocol::Http1).unwrap();
+        let req = Request::builder()
+            .method(method)
+            .uri(format!("http://{}/", gateway))
+            .body(body)
+            .unwrap();
+
+        let res = client.request(req).await.unwrap();
+        let status = res.status();
+        (status, res.into_body().collect().await.unwrap().to_bytes())
+    }
+
+    #[tokio::test]
+    async fn test_retries_connect_failure_on_another_server() {
+        let dead = closed_port().await;
+        let (live, hits) = counting_stub(StatusCode::OK, || {}).await;
+        let (backend, balancer) = retry_backend(&[dead, live], "{max_attempts: 2}");
+        let gateway = serve(&backend, balancer).await;
+
+        for _ in 0..2 {
+            let (status, _) = request(gateway, Method::GET, GatewayBody::Empty).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_retries_status_codes() {
+        let (failing, failing_hits) = counting_stub(StatusCode::SERVICE_UNAVAILABLE, || {}).await;
+        let (live, live_hits) = counting_stub(StatusCode::OK, || {}).await;
+        let (backend, balancer) =
+            retry_backend(&[failing, live], "{max_attempts: 3, status_codes: [503]}");
+        let gateway = serve(&backend, balancer).await;
+
+        let (status, _) = request(gateway, Method::GET, GatewayBody::Empty).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(failing_hits.load(Ordering::SeqCst), 1);
+        assert_eq!(live_hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_last_attempt_response_is_returned() {
+        let (failing, hits) = counting_stub(StatusCode::SERVICE_UNAVAILABLE, || {}).await;
+        let (backend, balancer) =
+            retry_backend(&[failing], "{max_attempts: 3, status_codes: [503]}");
+        let gateway = serve(&backend, balancer).await;
+
+        let (status, _) = request(gateway, Method::GET, GatewayBody::Empty).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retryable_response_is_kept_without_servers_left() {
+        let registry = Arc::new(OnceLock::<Arc<dyn LoadBalancer>>::new());
+
+        // The only server turns unhealthy while answering, so no server is left to retry on.
+        let marker = registry.clone();
+        let (unhealthy, hits) = counting_stub(StatusCode::SERVICE_UNAVAILABLE, move || {
+            let balancer = marker.get().unwrap();
+            for (server, _) in balancer.health().servers() {
+                balancer.health().set_healthy(&server, false);
+            }
+        })
+        .await;
+        let (backend, balancer) =
+            retry_backend(&[unhealthy], "{max_attempts: 3, status_codes: [503]}");
+        registry.set(balancer.clone()).ok().unwrap();
+        let gateway = serve(&backend, balancer).await;
+
+        let (status, _) = request(gateway, Method::GET, GatewayBody::Empty).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_idempotent_only() {
+        let (failing, hits) = counting_stub(StatusCode::SERVICE_UNAVAILABLE, || {}).await;
+        let (backend, balancer) =
+            retry_backend(&[failing], "{max_attempts: 3, status_codes: [503]}");
+        let gateway = serve(&backend, balancer).await;
+
+        let (status, _) = request(gateway, Method::POST, GatewayBody::Empty).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        let (failing, hits) = counting_stub(StatusCode::SERVICE_UNAVAILABLE, || {}).await;
+        let (backend, balancer) = retry_backend(
+            &[failing],
+            "{max_attempts: 3, status_codes: [503], idempotent_only: false}",
+        );
+        let gateway = serve(&backend, balancer).await;
+
+        let body = GatewayBody::Buffered(Full::new(Bytes::from("payload")));
+        let (status, body) = request(gateway, Method::POST, body).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body, "payload");
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_large_chunked_body_is_streamed_once() {
+        let (failing, hits) = counting_stub(StatusCode::SERVICE_UNAVAILABLE, || {}).await;
+        let (backend, balancer) = retry_backend(
+            &[failing],
+            "{max_attempts: 3, status_codes: [503], idempotent_only: false, max_body_bytes: 16}",
+        );
+        let gateway = serve(&backend, balancer).await;
+
+        // Without a known size, the client sends the body chunked.
+        let payload = Bytes::from("x".repeat(100));
+        let body = GatewayBody::Prefixed {
+            prefix: Some(payload.clone()),
+            body: Box::new(GatewayBody::Empty),
+        };
+        let (status, body) = request(gateway, Method::POST, body).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body, payload);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retries_keep_request_trailers() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let seen = received.clone();
+        let backend_addr = serve_h2c_stub(move |req: Request<Incoming>| {
+            let seen = seen.clone();
+            async move {
+                let body = req.into_body().collect().await.unwrap();
+                let checksum = body
+                    .trailers()
+                    .and_then(|trailers| trailers.get("x-checksum"));
+                let mut seen = seen.lock().unwrap();
+                seen.push(checksum.cloned());
+
+                let mut res = Response::new(Full::new(body.to_bytes()));
+                if seen.len() == 1 {
+                    *res.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+                }
+                res
+            }
+        })
+        .await;
+        let backend: Backend = serde_yaml::from_str(&format!(
+            "{{name: retry, protocol: H2c, servers: [{{server: \"http://{}\"}}], retry: {{max_attempts: 2, status_codes: [503], idempotent_only: false}}}}",
+            backend_addr
+        ))
+        .unwrap();
+        let balancer = LoadBalancerFactory::create(backend.lb_algorithm, backend.servers.clone());
+        let gateway = serve(&backend, balancer).await;
+
+        let mut stream = TcpStream::connect(gateway).await.unwrap();
+        stream
+            .write_all(
+                b"POST / HTTP/1.1\r\nHost: gateway\r\nTransfer-Encoding: chunked\r\n\
+                  Trailer: x-checksum\r\nConnection: close\r\n\r\n\
+                  7\r\npayload\r\n0\r\nx-checksum: abc\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.ends_with("payload"), "{}", response);
+        assert_eq!(
+            *received.lock().unwrap(),
+            [Some("abc".parse().unwrap()), Some("abc".parse().unwrap())]
+        );
+    }
+
+    #[test]
+    fn test_backoff() {
+        let retry: Retry =
+            serde_yaml::from_str("{backoff_base_ms: 10, backoff_max_ms: 30}").unwrap();
+        let retry = &retry;
+
+        let samples = |attempt| (0..200).map(move |_| backoff(retry, attempt));
+        assert!(samples(1).all(|delay| delay <= Duration::from_millis(10)));
+        assert!(samples(2).all(|delay| delay <= Duration::from_millis(20)));
+        assert!(samples(5).all(|delay| delay <= Duration::from_millis(30)));
+        assert!(samples(5).any(|delay| delay > Duration::from_millis(20)));
+        assert!(samples(5).any(|delay| delay < Duration::from_millis(10)));
+    }
+}
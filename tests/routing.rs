@@ -0,0 +1,230 @@
+#[cfg(test)]
+mod tests {
+    use http_body_util::BodyExt;
+    use hyper::{header::CONTENT_TYPE, Request, StatusCode};
+    use oxidegate::{
+        proxy_service::{
+            backend_url::BackendUrl, gateway_error::GatewayError, path_rewriter::PathRewriter,
+            predicates, proxy_bridge::request_host, router::Router,
+        },
+        types::{ErrorFormat, Frontend, RewriteRule, RoutePredicates, ValueMatch},
+    };
+
+    fn paths(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|path| path.to_string()).collect()
+    }
+
+    #[test]
+    fn test_most_specific_path_wins() {
+        let mut router = Router::new();
+        router.insert(&[], &paths(&["/*"]), 0, 0, "catch-all");
+        router.insert(&[], &paths(&["/api/*"]), 0, 0, "api");
+        router.insert(&[], &paths(&["/api/users/*"]), 0, 0, "users");
+        router.insert(&[], &paths(&["/api/users"]), 0, 0, "users-exact");
+
+        assert_eq!(router.lookup(None, "/"), Some(&"catch-all"));
+        assert_eq!(router.lookup(None, "/other"), Some(&"catch-all"));
+        assert_eq!(router.lookup(None, "/api"), Some(&"catch-all"));
+        assert_eq!(router.lookup(None, "/api/"), Some(&"api"));
+        assert_eq!(router.lookup(None, "/api/orders/1"), Some(&"api"));
+        assert_eq!(router.lookup(None, "/api/users"), Some(&"users-exact"));
+        assert_eq!(router.lookup(None, "/api/users/1"), Some(&"users"));
+    }
+
+    #[test]
+    fn test_exact_paths_and_priority() {
+        let mut router = Router::new();
+        router.insert(&[], &paths(&["/hello_world"]), 0, 0, "hello");
+        router.insert(&[], &paths(&["/"]), 0, 0, "root");
+
+        assert_eq!(router.lookup(None, "/hello_world"), Some(&"hello"));
+        assert_eq!(router.lookup(None, "/"), Some(&"root"));
+        assert_eq!(router.lookup(None, "/hello_world/x"), None);
+
+        router.insert(&[], &paths(&["/*"]), 10, 0, "override");
+        assert_eq!(router.lookup(None, "/hello_world"), Some(&"override"));
+    }
+
+    #[test]
+    fn test_host_matching() {
+        let mut router = Router::new();
+        router.insert(&[], &paths(&["/*"]), 0, 0, "default");
+        router.insert(&paths(&["*.example.com"]), &[], 0, 0, "wildcard");
+        router.insert(&paths(&["api.example.com"]), &[], 0, 0, "api");
+        router.insert(
+            &paths(&["api.example.com"]),
+            &paths(&["/v2/*"]),
+            0,
+            0,
+            "api-v2",
+        );
+
+        assert_eq!(
+            router.lookup(Some("API.example.com:8080"), "/"),
+            Some(&"api")
+        );
+        assert_eq!(
+            router.lookup(Some("api.example.com"), "/v2/x"),
+            Some(&"api-v2")
+        );
+        assert_eq!(
+            router.lookup(Some("app.example.com"), "/"),
+            Some(&"wildcard")
+        );
+        assert_eq!(
+            router.lookup(Some("a.b.example.com"), "/"),
+            Some(&"wildcard")
+        );
+        assert_eq!(router.lookup(Some("example.com"), "/"), Some(&"default"));
+        assert_eq!(router.lookup(None, "/"), Some(&"default"));
+    }
+
+    #[test]
+    fn test_request_host() {
+        let req = Request::get("/")
+            .header("host", "api.example.com:8080")
+            .body(())
+            .unwrap();
+        assert_eq!(request_host(&req), Some("api.example.com:8080"));
+
+        let req = Request::get("https://app.example.com/").body(()).unwrap();
+        assert_eq!(request_host(&req), Some("app.example.com"));
+
+        let req = Request::get("/").body(()).unwrap();
+        assert_eq!(request_host(&req), None);
+    }
+
+    #[test]
+    fn test_predicates() {
+        let value_match = |name: &str, value: Option<&str>| ValueMatch {
+            name: name.to_string(),
+            value: value.map(|value| value.to_string()),
+        };
+
+        let predicates = RoutePredicates {
+            methods: vec!["get".to_string(), "POST".to_string()],
+            headers: vec![value_match("x-api-version", Some("2"))],
+            query: vec![value_match("beta", None)],
+            cookies: vec![value_match("group", Some("internal"))],
+        };
+
+        let req = Request::post("/api?beta&page=2")
+            .header("X-Api-Version", "2")
+            .header("cookie", "session=abc; group=internal")
+            .body(())
+            .unwrap();
+        assert!(predicates::matches(&predicates, &req));
+
+        let req = Request::put("/api?beta")
+            .header("X-Api-Version", "2")
+            .header("cookie", "group=internal")
+            .body(())
+            .unwrap();
+        assert!(!predicates::matches(&predicates, &req));
+
+        let req = Request::get("/api?beta=1")
+            .header("X-Api-Version", "1")
+            .header("cookie", "group=internal")
+            .body(())
+            .unwrap();
+        assert!(!predicates::matches(&predicates, &req));
+
+        let req = Request::get("/api?page=1")
+            .header("X-Api-Version", "2")
+            .header("cookie", "group=internal")
+            .body(())
+            .unwrap();
+        assert!(!predicates::matches(&predicates, &req));
+
+        let predicates = RoutePredicates {
+            query: vec![value_match("name", Some("a b/c"))],
+            ..Default::default()
+        };
+        let req = Request::get("/?name=a+b%2Fc").body(()).unwrap();
+        assert!(predicates::matches(&predicates, &req));
+    }
+
+    #[test]
+    fn test_routes_with_predicates_are_more_specific() {
+        let mut router = Router::new();
+        router.insert(&[], &paths(&["/api/*"]), 0, 1, "v2");
+        router.insert(&[], &paths(&["/api/*"]), 0, 0, "v1");
+
+        assert_eq!(router.route(None, "/api/x", |_| true), Some(&"v2"));
+        assert_eq!(
+            router.route(None, "/api/x", |route| *route != "v2"),
+            Some(&"v1")
+        );
+    }
+
+    #[test]
+    fn test_path_rewriter() {
+        let frontend: Frontend = serde_yaml::from_str(
+            r#"
+            path_prefixes: ["/api/*"]
+            backend: "api"
+            strip_prefix: "/api/"
+            add_prefix: "/v2"
+            "#,
+        )
+        .unwrap();
+        let rewriter = PathRewriter::new(&frontend).unwrap();
+
+        assert_eq!(rewriter.rewrite("/api/users"), "/v2/users");
+        assert_eq!(rewriter.rewrite("/api"), "/v2");
+        assert_eq!(rewriter.rewrite("/apix/users"), "/v2/apix/users");
+
+        let frontend = Frontend {
+            strip_prefix: Some("/api".to_string()),
+            add_prefix: None,
+            rewrite: vec![RewriteRule {
+                pattern: r"^/users/(\d+)$".to_string(),
+                replacement: "/people/$1".to_string(),
+            }],
+            ..frontend
+        };
+        let rewriter = PathRewriter::new(&frontend).unwrap();
+
+        assert_eq!(rewriter.rewrite("/api/users/42"), "/people/42");
+        assert_eq!(rewriter.rewrite("/api/users/me"), "/users/me");
+        assert_eq!(rewriter.rewrite("/api"), "/");
+    }
+
+    #[test]
+    fn test_backend_url_join() {
+        let backend = BackendUrl::parse("http://host:8080").unwrap();
+        assert_eq!(
+            backend.join("/api/users", Some("page=2")).unwrap(),
+            "http://host:8080/api/users?page=2"
+        );
+
+        let backend = BackendUrl::parse("http://host:8080/v2/").unwrap();
+        assert_eq!(
+            backend.join("/api/users", None).unwrap(),
+            "http://host:8080/v2/api/users"
+        );
+        assert_eq!(backend.join("/", None).unwrap(), "http://host:8080/v2/");
+
+        assert!(BackendUrl::parse("host:8080").is_err());
+        assert!(BackendUrl::parse("ftp://host").is_err());
+        assert!(BackendUrl::parse("http://host/base?x=1").is_err());
+        assert!(BackendUrl::parse("http://host name").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_gateway_error_response() {
+        let res = GatewayError::NoRoute.into_response(ErrorFormat::Empty);
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert!(res.headers().get(CONTENT_TYPE).is_none());
+
+        let res = GatewayError::Timeout.into_response(ErrorFormat::Json);
+        assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(res.headers()[CONTENT_TYPE], "application/json");
+
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(
+            body,
+            r#"{"status":504,"error":"upstream_timeout","message":"The upstream server did not respond in time"}"#
+        );
+    }
+}
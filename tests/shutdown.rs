@@ -0,0 +1,169 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use http_body_util::BodyExt;
+    use hyper::{body::Bytes, Request, StatusCode};
+    use oxidegate::{
+        config::Config,
+        gateway::Gateway,
+        proxy_service::{gateway_body::GatewayBody, proxy_handler::build_client},
+        server::{
+            admin::{serve_admin, AdminState},
+            http::serve_http,
+            shutdown::Shutdown,
+        },
+        types::{ShutdownSettings, Timeouts, UpstreamProtocol},
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        task::JoinHandle,
+    };
+
+    use crate::common::{echo_upgrade, stub};
+
+    struct Running {
+        addr: SocketAddr,
+        admin: SocketAddr,
+        shutdown: Shutdown,
+        listener: JoinHandle<()>,
+    }
+
+    /// Serves a gateway proxying to `backend`, with its admin endpoints.
+    async fn gateway(backend: SocketAddr) -> Running {
+        let config: Config = serde_yaml::from_str(&format!(
+            "{{frontends: [{{backend: slow}}], backends: [{{name: slow, servers: [{{server: \"http://{}\"}}]}}]}}",
+            backend
+        ))
+        .unwrap();
+        let http2 = config.server.http2.clone();
+        let gateway = Arc::new(Gateway::new(config, None, None).unwrap());
+        let shutdown = Shutdown::new();
+
+        let admin_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let admin = admin_listener.local_addr().unwrap();
+        let state = Arc::new(AdminState::new(gateway.clone(), shutdown.clone()));
+        tokio::spawn(async move {
+            let _ = serve_admin(admin_listener, state).await;
+        });
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let serving = shutdown.clone();
+        let listener = tokio::spawn(async move {
+            serve_http(listener, &gateway, &serving, &http2)
+                .await
+                .unwrap();
+        });
+
+        Running {
+            addr,
+            admin,
+            shutdown,
+            listener,
+        }
+    }
+
+    async fn get(addr: SocketAddr, path: &str) -> (StatusCode, Bytes) {
+        let client = build_client(&Timeouts::default(), UpstreamProtocol::Http1).unwrap();
+        let req = Request::builder()
+            .uri(format!("http://{}{}", addr, path))
+            .body(GatewayBody::Empty)
+            .unwrap();
+        let res = client.request(req).await.unwrap();
+        let status = res.status();
+        (status, res.into_body().collect().await.unwrap().to_bytes())
+    }
+
+    fn settings(drain_timeout_ms: u64) -> ShutdownSettings {
+        serde_yaml::from_str(&format!("{{drain_timeout_ms: {}}}", drain_timeout_ms)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_trigger_stops_accepting() {
+        let running = gateway(stub("done", Duration::ZERO).await).await;
+        assert_eq!(get(running.addr, "/").await.0, StatusCode::OK);
+        assert_eq!(get(running.admin, "/ready").await.0, StatusCode::OK);
+
+        running.shutdown.trigger();
+        tokio::time::timeout(Duration::from_secs(1), running.listener)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(TcpStream::connect(running.addr).await.is_err());
+        let (status, body) = get(running.admin, "/ready").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body, "shutting down\n");
+    }
+
+    #[tokio::test]
+    async fn test_drain_waits_for_in_flight_requests() {
+        let running = gateway(stub("done", Duration::from_millis(300)).await).await;
+
+        let in_flight = tokio::spawn(get(running.addr, "/"));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(running.shutdown.active_connections(), 1);
+
+        let start = Instant::now();
+        running.shutdown.trigger();
+        assert_eq!(running.shutdown.drain(&settings(5000)).await, 0);
+        assert!(start.elapsed() >= Duration::from_millis(150));
+        assert_eq!(running.shutdown.active_connections(), 0);
+
+        assert_eq!(
+            in_flight.await.unwrap(),
+            (StatusCode::OK, Bytes::from("done"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_drain_timeout_force_closes() {
+        let running = gateway(stub("done", Duration::from_secs(10)).await).await;
+
+        let _in_flight = tokio::spawn(get(running.addr, "/"));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let start = Instant::now();
+        running.shutdown.trigger();
+        assert_eq!(running.shutdown.drain(&settings(100)).await, 1);
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_drain_waits_for_tunnels() {
+        let running = gateway(echo_upgrade().await).await;
+
+        let mut client = TcpStream::connect(running.addr).await.unwrap();
+        client
+            .write_all(
+                b"GET /socket HTTP/1.1\r\nHost: gateway\r\nConnection: Upgrade\r\nUpgrade: echo\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(client.read_u8().await.unwrap());
+        }
+        assert!(head.starts_with(b"HTTP/1.1 101"));
+
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        // The tunnel outlives the connection it was upgraded from.
+        running.shutdown.trigger();
+        assert_eq!(running.shutdown.drain(&settings(100)).await, 1);
+
+        drop(client);
+        assert_eq!(running.shutdown.drain(&settings(5000)).await, 0);
+    }
+}
@@ -0,0 +1,296 @@
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use http_body_util::{BodyExt, Full};
+    use hyper::{
+        body::{Bytes, Incoming},
+        server::conn::http1,
+        service::service_fn,
+        HeaderMap, Request, Response,
+    };
+    use hyper_util::rt::TokioIo;
+    use oxidegate::{
+        load_balancer::factory::LoadBalancerFactory,
+        proxy_service::{proxy_bridge::ProxyBridge, proxy_handler::ProxyHandler},
+        telemetry::{SpanContext, SpanKind, Tracer, TRACEPARENT, TRACESTATE},
+        types::{Backend, Frontend, ServerSettings, TracingSettings},
+    };
+    use serde_json::Value;
+    use tokio::{net::TcpListener, sync::mpsc};
+
+    const TRACEPARENT_VALUE: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    fn settings(endpoint: String, sampling_ratio: f64) -> TracingSettings {
+        TracingSettings {
+            endpoint,
+            service_name: "gateway-test".to_string(),
+            sampling_ratio,
+            batch_size: 512,
+            export_interval_ms: 50,
+        }
+    }
+
+    /// Accepts OTLP/HTTP exports and forwards the decoded bodies.
+    async fn collector() -> (String, mpsc::UnboundedReceiver<(String, Value)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req: Request<Incoming>| {
+                        let tx = tx.clone();
+                        async move {
+                            let path = req.uri().path().to_string();
+                            let body = req.into_body().collect().await.unwrap().to_bytes();
+                            let _ = tx.send((path, serde_json::from_slice(&body).unwrap()));
+                            Ok::<_, Infallible>(Response::new(Full::new(Bytes::new())))
+                        }
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        (format!("http://{}", addr), rx)
+    }
+
+    #[test]
+    fn test_extract_and_inject() {
+        let mut headers = HeaderMap::new();
+        headers.insert(TRACEPARENT, TRACEPARENT_VALUE.parse().unwrap());
+        headers.insert(TRACESTATE, "vendor=value".parse().unwrap());
+
+        let context = SpanContext::extract(&headers).unwrap();
+        assert!(context.sampled);
+        assert_eq!(context.trace_state.as_deref(), Some("vendor=value"));
+        assert_eq!(context.traceparent(), TRACEPARENT_VALUE);
+
+        let mut upstream = HeaderMap::new();
+        context.inject(&mut upstream);
+        assert_eq!(upstream[TRACEPARENT], TRACEPARENT_VALUE);
+        assert_eq!(upstream[TRACESTATE], "vendor=value");
+
+        for invalid in [
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+        ] {
+            headers.insert(TRACEPARENT, invalid.parse().unwrap());
+            assert_eq!(SpanContext::extract(&headers), None, "{}", invalid);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sampling_ratio() {
+        let (endpoint, _rx) = collector().await;
+
+        let never = Tracer::new(&settings(endpoint.clone(), 0.0)).unwrap();
+        let always = Tracer::new(&settings(endpoint, 1.0)).unwrap();
+        for _ in 0..100 {
+            assert!(
+                !never
+                    .start_span("GET", SpanKind::Server, None)
+                    .context()
+                    .unwrap()
+                    .sampled
+            );
+            assert!(
+                always
+                    .start_span("GET", SpanKind::Server, None)
+                    .context()
+                    .unwrap()
+                    .sampled
+            );
+        }
+
+        // A sampled parent wins over the local ratio.
+        let mut headers = HeaderMap::new();
+        headers.insert(TRACEPARENT, TRACEPARENT_VALUE.parse().unwrap());
+        let parent = SpanContext::extract(&headers).unwrap();
+        let span = never.start_span("GET", SpanKind::Server, Some(&parent));
+        let context = span.context().unwrap();
+        assert!(context.sampled);
+        assert_eq!(context.trace_id, parent.trace_id);
+        assert_ne!(context.span_id, parent.span_id);
+    }
+
+    #[tokio::test]
+    async fn test_export_to_collector() {
+        let (endpoint, mut rx) = collector().await;
+        let tracer = Tracer::new(&settings(endpoint, 1.0)).unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(TRACEPARENT, TRACEPARENT_VALUE.parse().unwrap());
+        let incoming = SpanContext::extract(&headers).unwrap();
+
+        let mut server = tracer.start_span("GET", SpanKind::Server, Some(&incoming));
+        server.set_attribute("http.response.status_code", 502u16);
+        server.set_error("502 Bad Gateway");
+        let mut client = server.parent().unwrap().child("GET", SpanKind::Client);
+        client.set_attribute("server.address", "http://10.0.0.1");
+        let server_id = server.context().unwrap().traceparent();
+        drop(client);
+        drop(server);
+
+        let (path, body) = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(path, "/v1/traces");
+
+        let resource = &body["resourceSpans"][0];
+        assert_eq!(
+            resource["resource"]["attributes"][0]["value"]["stringValue"],
+            "gateway-test"
+        );
+
+        let spans = resource["scopeSpans"][0]["spans"].as_array().unwrap();
+        assert_eq!(spans.len(), 2);
+
+        let client = &spans[0];
+        let server = &spans[1];
+        assert_eq!(client["kind"], 3);
+        assert_eq!(server["kind"], 2);
+        assert_eq!(server["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(server["parentSpanId"], "00f067aa0ba902b7");
+        assert_eq!(client["traceId"], server["traceId"]);
+        assert_eq!(client["parentSpanId"], server["spanId"]);
+        assert!(server_id.contains(server["spanId"].as_str().unwrap()));
+        assert_eq!(server["status"]["code"], 2);
+        assert_eq!(server["attributes"][0]["value"]["intValue"], "502");
+        assert_eq!(
+            client["attributes"][0]["value"]["stringValue"],
+            "http://10.0.0.1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_flush_exports_queued_spans() {
+        let (endpoint, mut rx) = collector().await;
+        let mut settings = settings(endpoint, 1.0);
+        settings.export_interval_ms = 60_000;
+        let tracer = Tracer::new(&settings).unwrap();
+
+        drop(tracer.start_span("GET", SpanKind::Server, None));
+        tracer.flush().await;
+
+        let (_, body) = rx.try_recv().unwrap();
+        let spans = body["resourceSpans"][0]["scopeSpans"][0]["spans"]
+            .as_array()
+            .unwrap();
+        assert_eq!(spans.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_proxied_request_is_traced() {
+        let (endpoint, mut rx) = collector().await;
+        let tracer = Tracer::new(&settings(endpoint, 1.0)).unwrap();
+
+        // Records the traceparent of the upstream request.
+        let received = Arc::new(Mutex::new(None));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = listener.local_addr().unwrap();
+        let backend_received = received.clone();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = service_fn(move |req: Request<Incoming>| {
+                *backend_received.lock().unwrap() = req.headers().get(TRACEPARENT).cloned();
+                async { Ok::<_, Infallible>(Response::new(Full::new(Bytes::new()))) }
+            });
+            let _ = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+
+        let backend: Backend = serde_yaml::from_str(&format!(
+            "{{name: traced, servers: [{{server: \"http://{}\"}}]}}",
+            backend_addr
+        ))
+        .unwrap();
+        let frontend: Frontend = serde_yaml::from_str("{name: api, backend: traced}").unwrap();
+        let balancer = LoadBalancerFactory::create(backend.lb_algorithm, backend.servers.clone());
+        let handler = ProxyHandler::new(&frontend, &backend, balancer, None).unwrap();
+        let bridge = Arc::new(
+            ProxyBridge::new(
+                Arc::new(vec![(frontend, Arc::new(handler))]),
+                &ServerSettings::default(),
+                None,
+                Some(tracer),
+            )
+            .unwrap(),
+        );
+
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(async move {
+            let service = service_fn(move |req| {
+                let bridge = bridge.clone();
+                async move {
+                    let peer = "127.0.0.1:40000".parse().unwrap();
+                    Ok::<_, Infallible>(bridge.determine(req, peer).await)
+                }
+            });
+            let _ = http1::Builder::new()
+                .serve_connection(TokioIo::new(server), service)
+                .await;
+        });
+        let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(client))
+            .await
+            .unwrap();
+        tokio::spawn(connection);
+
+        let req = Request::get("/orders")
+            .header("host", "gateway")
+            .header(TRACEPARENT, TRACEPARENT_VALUE)
+            .body(Full::new(Bytes::new()))
+            .unwrap();
+        let res = sender.send_request(req).await.unwrap();
+        assert!(res.status().is_success());
+        res.into_body().collect().await.unwrap();
+
+        // Spans can be exported in several batches.
+        let mut spans = Vec::new();
+        while spans.len() < 2 {
+            let (_, body) = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            spans.extend(
+                body["resourceSpans"][0]["scopeSpans"][0]["spans"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .cloned(),
+            );
+        }
+        let server = spans.iter().find(|span| span["kind"] == 2).unwrap();
+        let client = spans.iter().find(|span| span["kind"] == 3).unwrap();
+
+        assert_eq!(server["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(server["parentSpanId"], "00f067aa0ba902b7");
+        assert_eq!(client["traceId"], server["traceId"]);
+        assert_eq!(client["parentSpanId"], server["spanId"]);
+
+        let received = received.lock().unwrap().clone().unwrap();
+        assert_eq!(
+            received,
+            format!(
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-{}-01",
+                client["spanId"].as_str().unwrap()
+            )
+            .as_str()
+        );
+    }
+}
@@ -0,0 +1,183 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        net::SocketAddr,
+        pin::Pin,
+        sync::Arc,
+        task::{Context, Poll},
+        time::{Duration, Instant},
+    };
+
+    use http_body_util::{BodyExt, Full};
+    use hyper::{
+        body::{Body, Bytes, Frame, Incoming},
+        server::conn::http1,
+        service::service_fn,
+        Request, Response, StatusCode,
+    };
+    use hyper_util::rt::TokioIo;
+    use oxidegate::{
+        load_balancer::factory::LoadBalancerFactory,
+        proxy_service::{
+            context::RequestContext,
+            gateway_body::GatewayBody,
+            proxy_handler::{build_client, ProxyHandler},
+        },
+        types::{Backend, ErrorFormat, Frontend, Timeouts, UpstreamProtocol},
+    };
+    use tokio::net::{TcpListener, TcpSocket, TcpStream};
+
+    use crate::common::serve_stub;
+
+    /// A body sending one chunk, then ending or, when `stall` is set, never sending anything else.
+    struct Chunk {
+        data: Option<Bytes>,
+        stall: bool,
+    }
+
+    impl Body for Chunk {
+        type Data = Bytes;
+        type Error = Infallible;
+
+        fn poll_frame(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+            match self.data.take() {
+                Some(data) => Poll::Ready(Some(Ok(Frame::data(data)))),
+                None if self.stall => Poll::Pending,
+                None => Poll::Ready(None),
+            }
+        }
+    }
+
+    /// A backend answering after `delay`, with a body that stalls after its first chunk when
+    /// `stall` is set.
+    async fn backend(delay: Duration, stall: bool) -> SocketAddr {
+        serve_stub(move |_req| async move {
+            tokio::time::sleep(delay).await;
+            Response::new(Chunk {
+                data: Some(Bytes::from("first")),
+                stall,
+            })
+        })
+        .await
+    }
+
+    /// A listener whose accept queue is full, so connecting to it hangs.
+    async fn unresponsive() -> (SocketAddr, Vec<TcpStream>) {
+        let socket = TcpSocket::new_v4().unwrap();
+        socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let listener = socket.listen(0).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut queued = Vec::new();
+        for _ in 0..2 {
+            if let Ok(Ok(stream)) =
+                tokio::time::timeout(Duration::from_millis(100), TcpStream::connect(addr)).await
+            {
+                queued.push(stream);
+            }
+        }
+        std::mem::forget(listener);
+
+        (addr, queued)
+    }
+
+    /// Serves a frontend of a single `server` over HTTP/1.1.
+    async fn gateway(server: SocketAddr, timeouts: &str) -> SocketAddr {
+        let backend: Backend = serde_yaml::from_str(&format!(
+            "{{name: slow, servers: [{{server: \"http://{}\"}}], timeouts: {}}}",
+            server, timeouts
+        ))
+        .unwrap();
+        let frontend: Frontend = serde_yaml::from_str("{backend: slow}").unwrap();
+        let balancer = LoadBalancerFactory::create(backend.lb_algorithm, backend.servers.clone());
+        let handler = Arc::new(ProxyHandler::new(&frontend, &backend, balancer, None).unwrap());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req| {
+                        let handler = handler.clone();
+                        async move {
+                            let ctx = RequestContext {
+                                client_ip: "127.0.0.1".parse().unwrap(),
+                                request_id: "test".to_string(),
+                                stats: Default::default(),
+                                trace: None,
+                            };
+                            let res = match handler.handle(req, &ctx).await {
+                                Ok(res) => res,
+                                Err(e) => e.into_response(ErrorFormat::Json),
+                            };
+                            Ok::<_, Infallible>(res)
+                        }
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        addr
+    }
+
+    async fn get(gateway: SocketAddr) -> Response<Incoming> {
+        let client = build_client(&Timeouts::default(), UpstreamProtocol::Http1).unwrap();
+        let req = Request::builder()
+            .uri(format!("http://{}/", gateway))
+            .body(GatewayBody::Buffered(Full::new(Bytes::new())))
+            .unwrap();
+        client.request(req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_slow_backend_times_out() {
+        let server = backend(Duration::from_secs(5), false).await;
+        let gateway = gateway(server, "{request_timeout_ms: 100}").await;
+
+        let start = Instant::now();
+        let res = get(gateway).await;
+        assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert!(String::from_utf8_lossy(&body).contains("upstream_timeout"));
+    }
+
+    #[tokio::test]
+    async fn test_connect_timeout() {
+        let (server, _queued) = unresponsive().await;
+        let gateway = gateway(
+            server,
+            "{connect_timeout_ms: 100, request_timeout_ms: 5000}",
+        )
+        .await;
+
+        let start = Instant::now();
+        let res = get(gateway).await;
+        assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout_aborts_stalled_body() {
+        let server = backend(Duration::ZERO, true).await;
+        let gateway = gateway(server, "{idle_timeout_ms: 100}").await;
+
+        let start = Instant::now();
+        let res = get(gateway).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.into_body().collect().await.is_err());
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,152 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, io, net::SocketAddr, sync::Arc, time::Duration};
+
+    use hyper::{
+        header::{CONNECTION, UPGRADE},
+        server::conn::http1,
+        service::service_fn,
+        HeaderMap, Version,
+    };
+    use hyper_util::rt::TokioIo;
+    use oxidegate::{
+        load_balancer::factory::{LoadBalancer, LoadBalancerFactory},
+        metrics::metrics,
+        proxy_service::{context::RequestContext, proxy_handler::ProxyHandler, upgrade},
+        types::{Backend, Frontend},
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use crate::common::echo_upgrade;
+
+    /// Serves `handler` over HTTP/1.1 with upgrades.
+    async fn gateway(handler: Arc<ProxyHandler>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req| {
+                        let handler = handler.clone();
+                        async move {
+                            let ctx = RequestContext {
+                                client_ip: "127.0.0.1".parse().unwrap(),
+                                request_id: "test".to_string(),
+                                stats: Default::default(),
+                                trace: None,
+                            };
+                            Ok::<_, Infallible>(handler.handle(req, &ctx).await.unwrap())
+                        }
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .with_upgrades()
+                        .await;
+                });
+            }
+        });
+
+        addr
+    }
+
+    async fn connections(balancer: &Arc<dyn LoadBalancer>, expected: usize) -> usize {
+        for _ in 0..100 {
+            if balancer.connections()[0].1 == expected {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        balancer.connections()[0].1
+    }
+
+    #[test]
+    fn test_requested_upgrade() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONNECTION, "keep-alive, Upgrade".parse().unwrap());
+        headers.insert(UPGRADE, "websocket".parse().unwrap());
+        assert_eq!(
+            upgrade::requested(Version::HTTP_11, &headers).unwrap(),
+            "websocket"
+        );
+        assert_eq!(upgrade::requested(Version::HTTP_2, &headers), None);
+
+        headers.insert(UPGRADE, "h2c".parse().unwrap());
+        assert_eq!(upgrade::requested(Version::HTTP_11, &headers), None);
+
+        headers.insert(UPGRADE, "websocket".parse().unwrap());
+        headers.insert(CONNECTION, "keep-alive".parse().unwrap());
+        assert_eq!(upgrade::requested(Version::HTTP_11, &headers), None);
+    }
+
+    #[tokio::test]
+    async fn test_tunnel_idle_timeout() {
+        let (client, mut client_peer) = tokio::io::duplex(64);
+        let (upstream, mut upstream_peer) = tokio::io::duplex(64);
+
+        let tunnel = tokio::spawn(upgrade::copy_bidirectional(
+            client,
+            upstream,
+            Duration::from_millis(100),
+        ));
+
+        client_peer.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        upstream_peer.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        let err = tunnel.await.unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn test_websocket_tunnel() {
+        let backend_addr = echo_upgrade().await;
+        let backend: Backend = serde_yaml::from_str(&format!(
+            "{{name: ws, lb_algorithm: LeastConnections, servers: [{{server: \"http://{}\"}}]}}",
+            backend_addr
+        ))
+        .unwrap();
+        let frontend: Frontend = serde_yaml::from_str("{backend: ws}").unwrap();
+        let balancer = LoadBalancerFactory::create(backend.lb_algorithm, backend.servers.clone());
+        let handler = ProxyHandler::new(&frontend, &backend, balancer.clone(), None).unwrap();
+        let gateway_addr = gateway(Arc::new(handler)).await;
+
+        let mut client = TcpStream::connect(gateway_addr).await.unwrap();
+        client
+            .write_all(
+                b"GET /socket HTTP/1.1\r\nHost: gateway\r\nConnection: Upgrade\r\nUpgrade: echo\r\n\r\n",
+            )
+            .await
+            .unwrap();
+
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(client.read_u8().await.unwrap());
+        }
+        let head = String::from_utf8(head).unwrap().to_ascii_lowercase();
+        assert!(head.starts_with("http/1.1 101"), "{}", head);
+        assert!(head.contains("upgrade: echo\r\n"), "{}", head);
+        assert!(head.contains("connection: upgrade\r\n"), "{}", head);
+
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        assert_eq!(connections(&balancer, 1).await, 1);
+        assert!(metrics()
+            .render()
+            .contains("oxidegate_upgraded_connections 1"));
+
+        drop(client);
+        assert_eq!(connections(&balancer, 0).await, 0);
+    }
+}
@@ -0,0 +1,182 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        future::{self},
+        sync::Arc,
+    };
+
+    use http_body_util::{BodyExt, Full};
+    use hyper::{
+        body::{Body, Bytes, Incoming},
+        HeaderMap, Request, Response, Version,
+    };
+
+    use oxidegate::{
+        load_balancer::factory::LoadBalancerFactory,
+        proxy_service::{
+            backend_url::BackendUrl,
+            context::RequestContext,
+            gateway_body::GatewayBody,
+            proxy_handler::{build_client, ProxyHandler},
+        },
+        types::{Backend, BackendServer, Frontend, Timeouts, UpstreamProtocol},
+    };
+
+    use crate::common::serve_h2c_stub;
+
+    /// A body of `data` followed by a `grpc-status: 0` trailer.
+    fn grpc_body(data: &'static str) -> impl Body<Data = Bytes, Error = Infallible> {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", "0".parse().unwrap());
+
+        Full::new(Bytes::from(data)).with_trailers(future::ready(Some(Ok(trailers))))
+    }
+
+    #[tokio::test]
+    async fn test_h2c_client_receives_trailers() {
+        let addr = serve_h2c_stub(|_req| async { Response::new(grpc_body("reply")) }).await;
+
+        let client = build_client(&Timeouts::default(), UpstreamProtocol::H2c).unwrap();
+        let req = Request::builder()
+            .uri(format!("http://{}/", addr))
+            .body(GatewayBody::Empty)
+            .unwrap();
+
+        let res = client.request(req).await.unwrap();
+        assert_eq!(res.version(), Version::HTTP_2);
+
+        let collected = res.into_body().collect().await.unwrap();
+        assert_eq!(collected.trailers().unwrap()["grpc-status"], "0");
+        assert_eq!(collected.to_bytes(), "reply");
+    }
+
+    #[tokio::test]
+    async fn test_proxy_forwards_trailers_over_h2c() {
+        // Reports the version and `te` header it received.
+        let backend_addr = serve_h2c_stub(|req: Request<Incoming>| async move {
+            let te = req.headers().get("te").cloned();
+
+            let mut res = Response::new(grpc_body("reply"));
+            res.headers_mut().insert(
+                "x-upstream-version",
+                format!("{:?}", req.version()).parse().unwrap(),
+            );
+            if let Some(te) = te {
+                res.headers_mut().insert("x-upstream-te", te);
+            }
+            res
+        })
+        .await;
+
+        let backend: Backend = serde_yaml::from_str(&format!(
+            "{{name: grpc, protocol: H2c, servers: [{{server: \"http://{}\"}}]}}",
+            backend_addr
+        ))
+        .unwrap();
+        let frontend: Frontend =
+            serde_yaml::from_str("{path_prefixes: [\"/*\"], backend: grpc}").unwrap();
+        let balancer = LoadBalancerFactory::create(backend.lb_algorithm, backend.servers.clone());
+        let handler = Arc::new(ProxyHandler::new(&frontend, &backend, balancer, None).unwrap());
+
+        let gateway_addr = serve_h2c_stub(move |req| {
+            let handler = handler.clone();
+            async move {
+                let ctx = RequestContext {
+                    client_ip: "127.0.0.1".parse().unwrap(),
+                    request_id: "test".to_string(),
+                    stats: Default::default(),
+                    trace: None,
+                };
+                handler.handle(req, &ctx).await.unwrap()
+            }
+        })
+        .await;
+
+        let client = build_client(&Timeouts::default(), UpstreamProtocol::H2c).unwrap();
+        let req = Request::builder()
+            .method("POST")
+            .uri(format!(
+                "http://{}/helloworld.Greeter/SayHello",
+                gateway_addr
+            ))
+            .header("te", "trailers")
+            .header("content-type", "application/grpc")
+            .body(GatewayBody::Buffered(Full::new(Bytes::from("request"))))
+            .unwrap();
+
+        let res = client.request(req).await.unwrap();
+        assert_eq!(res.headers()["x-upstream-version"], "HTTP/2.0");
+        assert_eq!(res.headers()["x-upstream-te"], "trailers");
+        assert_eq!(res.headers()["via"], "2 oxidegate");
+
+        let collected = res.into_body().collect().await.unwrap();
+        assert_eq!(collected.trailers().unwrap()["grpc-status"], "0");
+        assert_eq!(collected.to_bytes(), "reply");
+    }
+
+    #[tokio::test]
+    async fn test_proxies_to_servers_added_at_runtime() {
+        let server = |name: &'static str| {
+            serve_h2c_stub(move |_req| async move { Response::new(Full::new(Bytes::from(name))) })
+        };
+        let first = server("first").await;
+        let second = server("second").await;
+
+        let backend: Backend = serde_yaml::from_str(&format!(
+            "{{name: dynamic, protocol: H2c, servers: [{{server: \"http://{}\"}}]}}",
+            first
+        ))
+        .unwrap();
+        let frontend: Frontend = serde_yaml::from_str("{backend: dynamic}").unwrap();
+        let balancer = LoadBalancerFactory::create(backend.lb_algorithm, backend.servers.clone());
+        let handler =
+            Arc::new(ProxyHandler::new(&frontend, &backend, balancer.clone(), None).unwrap());
+
+        let gateway_addr = serve_h2c_stub(move |req| {
+            let handler = handler.clone();
+            async move {
+                let ctx = RequestContext {
+                    client_ip: "127.0.0.1".parse().unwrap(),
+                    request_id: "test".to_string(),
+                    stats: Default::default(),
+                    trace: None,
+                };
+                handler.handle(req, &ctx).await.unwrap()
+            }
+        })
+        .await;
+
+        let client = build_client(&Timeouts::default(), UpstreamProtocol::H2c).unwrap();
+        let get = || async {
+            let req = Request::builder()
+                .uri(format!("http://{}/", gateway_addr))
+                .body(GatewayBody::Empty)
+                .unwrap();
+            let res = client.request(req).await.unwrap();
+            res.into_body().collect().await.unwrap().to_bytes()
+        };
+
+        assert_eq!(get().await, "first");
+        balancer.set_servers(vec![BackendServer {
+            server: format!("http://{}", second),
+            weight: None,
+        }]);
+        assert_eq!(get().await, "second");
+    }
+
+    #[test]
+    fn test_protocol_requires_matching_scheme() {
+        let http = BackendUrl::parse("http://10.0.0.1:50051").unwrap();
+        let https = BackendUrl::parse("https://10.0.0.1:50051").unwrap();
+
+        assert!(http.check_protocol(UpstreamProtocol::H2c).is_ok());
+        assert!(http.check_protocol(UpstreamProtocol::Http2).is_err());
+        assert!(http.check_protocol(UpstreamProtocol::Auto).is_ok());
+        assert!(https.check_protocol(UpstreamProtocol::Http2).is_ok());
+        assert!(https.check_protocol(UpstreamProtocol::H2c).is_err());
+        assert!(https.check_protocol(UpstreamProtocol::Http1).is_ok());
+    }
+}